@@ -0,0 +1,826 @@
+#![no_std]
+
+//! Rules and strategies of the pebbles game without the Gear runtime, shared by
+//! the on-chain program and off-chain tools.
+
+extern crate alloc;
+
+use alloc::{boxed::Box, collections::BTreeMap, vec, vec::Vec};
+use parity_scale_codec::{Decode, Encode};
+use scale_info::TypeInfo;
+
+/// Largest heap allowed together with `allowed_moves`, bounding the Grundy table
+pub const MAX_ALLOWED_MOVES_HEAP: u32 = 10_000;
+
+/// Most distinct moves in `allowed_moves`, bounding the cost of a Grundy table
+pub const MAX_ALLOWED_MOVES: usize = 32;
+
+#[derive(Debug, Default, Clone, Encode, Decode, TypeInfo)]
+pub struct PebblesInit {
+    pub difficulty: DifficultyLevel,
+    pub pebbles_count: u32,
+    pub max_pebbles_per_turn: u32,
+    pub win_condition: WinCondition,
+    /// Heap sizes for multi-heap Nim; empty means a single heap of `pebbles_count`
+    pub heaps: Vec<u32>,
+    /// Pebble counts a turn may take, e.g. `[1, 3, 4]`; empty means
+    /// `1..=max_pebbles_per_turn`. When set, `max_pebbles_per_turn` is ignored
+    pub allowed_moves: Vec<u32>,
+    pub rule_set: RuleSet,
+    /// Debug only: when set at program init, all randomness (including who moves
+    /// first) comes from a [`SeededRng`] with this seed instead of `exec::random`,
+    /// so games can be reproduced exactly
+    pub seed: Option<u64>,
+    /// Blocks a player has for each turn before the game ends as if they gave up;
+    /// 0 disables timeouts. Only read from the program's init config
+    pub turn_timeout: u32,
+}
+
+#[derive(Debug, Default, Clone, Encode, Decode, TypeInfo)]
+pub enum RuleSet {
+    /// Every turn is bounded by `max_pebbles_per_turn` or `allowed_moves`
+    #[default]
+    Standard,
+    /// Fibonacci Nim on a single heap: the first turn may take anything but the
+    /// whole heap, every later turn at most twice the previous one.
+    /// `max_pebbles_per_turn` is ignored
+    Fibonacci,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, TypeInfo)]
+pub enum WinCondition {
+    /// The player who takes the last pebble wins
+    #[default]
+    Normal,
+    /// The player who takes the last pebble loses
+    Misere,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, TypeInfo)]
+pub enum DifficultyLevel {
+    #[default]
+    Easy,
+    /// Plays the optimal move with `optimal_percent`% probability, randomly otherwise
+    Medium {
+        optimal_percent: u8,
+    },
+    Hard,
+    /// Like `Medium`, with the probability tuned from the player's recent results
+    Adaptive,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+pub enum PebblesError {
+    /// `pebbles_count` in `PebblesInit` or `Restart`, or one of the `heaps`, is 0
+    InvalidPebblesCount,
+    /// `max_pebbles_per_turn` is 0 or greater than the total number of pebbles
+    InvalidMaxPebblesPerTurn,
+    /// `Turn(0)`
+    ZeroPebbles,
+    /// The turn takes more than `current_max`
+    TooManyPebbles,
+    /// The turn takes a count missing from `allowed_moves`
+    MoveNotAllowed,
+    /// The turn takes more than the heap holds
+    NotEnoughPebbles,
+    /// The game already has a winner
+    GameOver,
+    /// No game with this id belongs to the sender, or the sender has no duel
+    NoActiveGame,
+    /// `allowed_moves` contains 0, more than `MAX_ALLOWED_MOVES` distinct moves, or
+    /// no move fits any heap
+    InvalidAllowedMoves,
+    /// A heap exceeds `MAX_ALLOWED_MOVES_HEAP` while `allowed_moves` is set
+    HeapTooLarge,
+    /// `TurnOnHeap` names a heap that does not exist
+    InvalidHeap,
+    /// Misère play and `RuleSet::Fibonacci` only support a single heap with the
+    /// default moves; Fibonacci Nim is played under the normal win condition
+    UnsupportedRules,
+    /// `Restart` of a wagered or series game that is still being played,
+    /// `Rematch` of an unfinished game, or a PvP challenge, `AcceptChallenge`
+    /// or `JoinQueue` involving someone whose duel is not finished
+    GameInProgress,
+    /// A PvP player moved while it was the other player's turn
+    NotYourTurn,
+    /// `AcceptChallenge` names an account without an open challenge
+    NoChallenge,
+    /// `AcceptChallenge` names the sender's own challenge
+    CannotChallengeSelf,
+    /// The program's balance cannot cover the payout of the wager
+    InsufficientBankroll,
+    /// `AcceptChallenge` carries a value different from the challenger's stake,
+    /// or a tournament registration one different from the entry fee
+    WrongStake,
+    /// A timeout check for a game whose turn deadline has not passed, or
+    /// `StartTournament` before the registration deadline
+    NotTimedOut,
+    /// `LeaveQueue` or an expiry check for an account that is not in the lobby
+    NotQueued,
+    /// No tournament with this id
+    NoTournament,
+    /// Registration for a tournament whose deadline has passed, or
+    /// `StartTournament` for one that has already started
+    RegistrationClosed,
+    AlreadyRegistered,
+    /// The tournament has as many players as the program allows
+    TournamentFull,
+    /// `CreateTournament` with a registration deadline that is not in the future
+    /// or further away than the program allows
+    InvalidDeadline,
+    /// `StartSeries` with an even number of games, which could end in a tie
+    InvalidSeriesLength,
+    /// A staked challenge or a tournament with an entry fee while
+    /// `PebblesInit::turn_timeout` is 0, so a stalled game would lock the funds
+    NoTurnTimeout,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+pub enum Player {
+    #[default]
+    User,
+    Program,
+    /// The account that accepted a PvP challenge; the challenger plays as `User`
+    Opponent,
+}
+
+#[derive(Debug, Default, Clone, Encode, Decode, TypeInfo)]
+pub struct Game {
+    pub pebbles_count: u32,
+    pub max_pebbles_per_turn: u32,
+    /// Most pebbles the next turn may take; changes after every move under `RuleSet::Fibonacci`
+    pub current_max: u32,
+    pub rule_set: RuleSet,
+    /// Total over all heaps
+    pub pebbles_remaining: u32,
+    pub heaps_remaining: Vec<u32>,
+    /// Sorted; empty means `1..=max_pebbles_per_turn`
+    pub allowed_moves: Vec<u32>,
+    pub difficulty: DifficultyLevel,
+    pub win_condition: WinCondition,
+    pub first_player: Player,
+    /// Who plays against `User`: `Program`, or `Opponent` in a PvP game
+    pub rival: Player,
+    pub winner: Option<Player>,
+    /// Every turn of the game, including the program's counter-turns
+    pub moves: Vec<Move>,
+    /// Value each player put in escrow; 0 for a free game
+    pub stake: u128,
+    /// Block height the game started at
+    pub started_at: u32,
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct Move {
+    pub player: Player,
+    pub heap: u32,
+    pub pebbles: u32,
+    /// Pebbles left on all heaps after this move
+    pub remaining: u32,
+    pub block_height: u32,
+}
+
+/// Heap sizes at the start; a single heap of `pebbles_count` unless `heaps` is set
+fn initial_heaps(params: &PebblesInit) -> Vec<u32> {
+    if params.heaps.is_empty() {
+        vec![params.pebbles_count]
+    } else {
+        params.heaps.clone()
+    }
+}
+
+/// `allowed_moves` sorted and deduplicated
+fn normalized_moves(params: &PebblesInit) -> Vec<u32> {
+    let mut moves = params.allowed_moves.clone();
+    moves.sort_unstable();
+    moves.dedup();
+    moves
+}
+
+pub fn validate_params(params: &PebblesInit) -> Result<(), PebblesError> {
+    let heaps = initial_heaps(params);
+    if heaps.contains(&0) {
+        return Err(PebblesError::InvalidPebblesCount);
+    }
+    let total: u32 = heaps.iter().sum();
+    let moves = normalized_moves(params);
+    if let RuleSet::Fibonacci = params.rule_set {
+        // The first turn may not take the whole heap, so it needs at least 2 pebbles
+        if heaps.len() > 1 || !moves.is_empty() || params.win_condition != WinCondition::Normal {
+            return Err(PebblesError::UnsupportedRules);
+        }
+        if total < 2 {
+            return Err(PebblesError::InvalidPebblesCount);
+        }
+    } else if moves.is_empty() {
+        if params.max_pebbles_per_turn == 0 || params.max_pebbles_per_turn > total {
+            return Err(PebblesError::InvalidMaxPebblesPerTurn);
+        }
+    } else {
+        let largest = heaps.iter().copied().max().unwrap_or_default();
+        if moves[0] == 0 || moves[0] > largest || moves.len() > MAX_ALLOWED_MOVES {
+            return Err(PebblesError::InvalidAllowedMoves);
+        }
+        if largest > MAX_ALLOWED_MOVES_HEAP {
+            return Err(PebblesError::HeapTooLarge);
+        }
+    }
+    if heaps.len() > 1 && matches!(params.win_condition, WinCondition::Misere) {
+        return Err(PebblesError::UnsupportedRules);
+    }
+    Ok(())
+}
+
+impl Game {
+    /// Validates `params` and sets up a game against the program in which
+    /// `first_player` moves first
+    pub fn new(params: PebblesInit, first_player: Player) -> Result<Self, PebblesError> {
+        Self::with_rival(params, first_player, Player::Program)
+    }
+
+    /// Like [`Game::new`], for a PvP game between `User` and `Opponent`
+    pub fn new_pvp(params: PebblesInit, first_player: Player) -> Result<Self, PebblesError> {
+        Self::with_rival(params, first_player, Player::Opponent)
+    }
+
+    fn with_rival(
+        params: PebblesInit,
+        first_player: Player,
+        rival: Player,
+    ) -> Result<Self, PebblesError> {
+        validate_params(&params)?;
+
+        let heaps = initial_heaps(&params);
+        let total = heaps.iter().sum();
+        let allowed_moves = normalized_moves(&params);
+        // With a custom set of moves, the largest one
+        let max_pebbles_per_turn = allowed_moves
+            .last()
+            .copied()
+            .unwrap_or(params.max_pebbles_per_turn);
+        let current_max = match params.rule_set {
+            // The first turn may take anything but the whole heap
+            RuleSet::Fibonacci => total - 1,
+            RuleSet::Standard => max_pebbles_per_turn,
+        };
+
+        Ok(Self {
+            pebbles_count: total,
+            max_pebbles_per_turn,
+            current_max,
+            rule_set: params.rule_set,
+            pebbles_remaining: total,
+            heaps_remaining: heaps,
+            allowed_moves,
+            difficulty: params.difficulty,
+            win_condition: params.win_condition,
+            first_player,
+            rival,
+            winner: None,
+            moves: Vec::new(),
+            stake: 0,
+            started_at: 0,
+        })
+    }
+
+    /// Parameters that set up this game again, with the heaps it started with
+    pub fn params(&self) -> PebblesInit {
+        let mut heaps = self.heaps_remaining.clone();
+        for played in &self.moves {
+            heaps[played.heap as usize] += played.pebbles;
+        }
+        if heaps.len() == 1 {
+            heaps.clear();
+        }
+        PebblesInit {
+            difficulty: self.difficulty.clone(),
+            pebbles_count: self.pebbles_count,
+            max_pebbles_per_turn: self.max_pebbles_per_turn,
+            win_condition: self.win_condition.clone(),
+            heaps,
+            allowed_moves: self.allowed_moves.clone(),
+            rule_set: self.rule_set.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn winner(&self) -> Option<&Player> {
+        self.winner.as_ref()
+    }
+
+    /// The other side of the game from `player`
+    pub fn opponent_of(&self, player: &Player) -> Player {
+        match player {
+            Player::User => self.rival.clone(),
+            Player::Program | Player::Opponent => Player::User,
+        }
+    }
+
+    /// Block height of the latest move, or of the start when nobody has moved yet
+    pub fn last_move_at(&self) -> u32 {
+        self.moves
+            .last()
+            .map_or(self.started_at, |last| last.block_height)
+    }
+
+    /// Whose turn it is
+    pub fn next_player(&self) -> Player {
+        match self.moves.last() {
+            Some(last) => self.opponent_of(&last.player),
+            None => self.first_player.clone(),
+        }
+    }
+
+    fn is_allowed(&self, pebbles: u32) -> bool {
+        if self.allowed_moves.is_empty() {
+            pebbles <= self.current_max
+        } else {
+            self.allowed_moves.binary_search(&pebbles).is_ok()
+        }
+    }
+
+    pub fn validate_move(&self, heap: u32, pebbles: u32) -> Result<(), PebblesError> {
+        if self.winner.is_some() {
+            return Err(PebblesError::GameOver);
+        }
+        let remaining = *self
+            .heaps_remaining
+            .get(heap as usize)
+            .ok_or(PebblesError::InvalidHeap)?;
+        if pebbles == 0 {
+            return Err(PebblesError::ZeroPebbles);
+        }
+        if pebbles > self.current_max {
+            return Err(PebblesError::TooManyPebbles);
+        }
+        if !self.is_allowed(pebbles) {
+            return Err(PebblesError::MoveNotAllowed);
+        }
+        if pebbles > remaining {
+            return Err(PebblesError::NotEnoughPebbles);
+        }
+        Ok(())
+    }
+
+    /// Takes `pebbles` from `heap` on behalf of `player` and records the move.
+    /// When the next player has no legal move left, the winner is decided by
+    /// `win_condition`
+    pub fn apply_move(
+        &mut self,
+        player: Player,
+        heap: u32,
+        pebbles: u32,
+        block_height: u32,
+    ) -> Result<(), PebblesError> {
+        if self.winner.is_none() && player != self.next_player() {
+            return Err(PebblesError::NotYourTurn);
+        }
+        self.validate_move(heap, pebbles)?;
+
+        self.heaps_remaining[heap as usize] -= pebbles;
+        self.pebbles_remaining -= pebbles;
+        self.moves.push(Move {
+            player: player.clone(),
+            heap,
+            pebbles,
+            remaining: self.pebbles_remaining,
+            block_height,
+        });
+        if let RuleSet::Fibonacci = self.rule_set {
+            self.current_max = pebbles.saturating_mul(2);
+        }
+        if !self.has_legal_move() {
+            self.winner = Some(match self.win_condition {
+                WinCondition::Normal => player,
+                WinCondition::Misere => self.opponent_of(&player),
+            });
+        }
+        Ok(())
+    }
+
+    /// Ends the game in favour of the opponent of `player`
+    pub fn give_up(&mut self, player: &Player) -> Result<(), PebblesError> {
+        if self.winner.is_some() {
+            return Err(PebblesError::GameOver);
+        }
+        self.winner = Some(self.opponent_of(player));
+        Ok(())
+    }
+
+    /// Pebble counts a turn may take from a heap holding `remaining`, ascending
+    pub fn heap_moves(&self, remaining: u32) -> Vec<u32> {
+        if self.allowed_moves.is_empty() {
+            (1..=self.current_max.min(remaining)).collect()
+        } else {
+            self.allowed_moves
+                .iter()
+                .copied()
+                .take_while(|pebbles| *pebbles <= remaining)
+                .collect()
+        }
+    }
+
+    pub fn has_legal_move(&self) -> bool {
+        let smallest = self.allowed_moves.first().copied().unwrap_or(1);
+        self.heaps_remaining.iter().any(|heap| *heap >= smallest)
+    }
+
+    /// `(heap, pebbles)` pairs for the next turn; empty once the game is over
+    pub fn legal_moves(&self) -> Vec<(u32, u32)> {
+        if self.winner.is_some() {
+            return Vec::new();
+        }
+        self.heaps_remaining
+            .iter()
+            .enumerate()
+            .flat_map(|(heap, remaining)| {
+                self.heap_moves(*remaining)
+                    .into_iter()
+                    .map(move |count| (heap as u32, count))
+            })
+            .collect()
+    }
+
+    /// Largest heap the Grundy table has to cover
+    pub fn max_heap(&self) -> u32 {
+        self.heaps_remaining
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// Value of every heap size up to `max_heap` for a custom set of moves: the Grundy
+/// number under `WinCondition::Normal`; under `WinCondition::Misere` (single heap
+/// only) 0 if the player to move loses and 1 if they win. A player with no legal
+/// move loses under normal play and wins under misère play
+pub fn grundy_table(moves: &[u32], win_condition: &WinCondition, max_heap: u32) -> Vec<u32> {
+    let mut table: Vec<u32> = Vec::with_capacity(max_heap as usize + 1);
+    for heap in 0..=max_heap {
+        let reachable: Vec<u32> = moves
+            .iter()
+            .take_while(|pebbles| **pebbles <= heap)
+            .map(|pebbles| table[(heap - pebbles) as usize])
+            .collect();
+        let value = match win_condition {
+            WinCondition::Normal => (0..).find(|value| !reachable.contains(value)).unwrap(),
+            WinCondition::Misere => {
+                if reachable.is_empty() || reachable.contains(&0) {
+                    1
+                } else {
+                    0
+                }
+            }
+        };
+        table.push(value);
+    }
+    table
+}
+
+/// Smallest term of the Zeckendorf representation of `n`, i.e. of `n` written as
+/// a sum of non-consecutive Fibonacci numbers
+fn smallest_zeckendorf_term(mut n: u32) -> u32 {
+    let mut fibs = vec![1u32, 2];
+    while let Some(next) = fibs[fibs.len() - 1].checked_add(fibs[fibs.len() - 2]) {
+        if next > n {
+            break;
+        }
+        fibs.push(next);
+    }
+    let mut smallest = n;
+    for fib in fibs.into_iter().rev() {
+        if fib <= n {
+            n -= fib;
+            smallest = fib;
+        }
+    }
+    smallest
+}
+
+/// The optimal move as `(heap, pebbles, winning)`.
+///
+/// With the default moves every heap's Grundy value is `heap % (max_pebbles_per_turn + 1)`
+/// and normal play zeroes the nim-sum; single-heap misère play leaves
+/// `1 (mod max_pebbles_per_turn + 1)` pebbles. Custom sets of moves look values up in
+/// `grundy` (see [`grundy_table`]; unused otherwise). Fibonacci Nim takes the smallest
+/// Zeckendorf term of the pile, which wins whenever it fits `current_max`.
+/// In a lost position the smallest move from the largest heap delays the game
+pub fn optimal_move(game: &Game, grundy: &[u32]) -> (u32, u32, bool) {
+    let period = game.max_pebbles_per_turn + 1;
+    let largest = game
+        .heaps_remaining
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, heap)| **heap)
+        .map_or(0, |(index, _)| index as u32);
+    let delaying_move = (
+        largest,
+        game.heap_moves(game.heaps_remaining[largest as usize])[0],
+        false,
+    );
+
+    if let RuleSet::Fibonacci = game.rule_set {
+        let term = smallest_zeckendorf_term(game.pebbles_remaining);
+        return if term <= game.current_max {
+            (0, term, true)
+        } else {
+            delaying_move
+        };
+    }
+
+    if !game.allowed_moves.is_empty() {
+        let nim_sum = game
+            .heaps_remaining
+            .iter()
+            .fold(0, |sum, heap| sum ^ grundy[*heap as usize]);
+        if nim_sum == 0 {
+            return delaying_move;
+        }
+        return game
+            .heaps_remaining
+            .iter()
+            .enumerate()
+            .find_map(|(index, heap)| {
+                let target = grundy[*heap as usize] ^ nim_sum;
+                game.heap_moves(*heap)
+                    .into_iter()
+                    .find(|pebbles| grundy[(heap - pebbles) as usize] == target)
+                    .map(|pebbles| (index as u32, pebbles, true))
+            })
+            .expect("A non-zero nim-sum always has a winning move");
+    }
+
+    if let WinCondition::Misere = game.win_condition {
+        let winning = (game.pebbles_remaining - 1) % period;
+        return if winning == 0 {
+            delaying_move
+        } else {
+            (0, winning, true)
+        };
+    }
+
+    let nim_sum = game
+        .heaps_remaining
+        .iter()
+        .fold(0, |sum, heap| sum ^ (heap % period));
+    if nim_sum == 0 {
+        return delaying_move;
+    }
+    game.heaps_remaining
+        .iter()
+        .enumerate()
+        .find_map(|(index, heap)| {
+            let grundy = heap % period;
+            let target = grundy ^ nim_sum;
+            (target < grundy).then(|| (index as u32, grundy - target, true))
+        })
+        .expect("A non-zero nim-sum always has a winning move")
+}
+
+/// A legal move picked by `random`: first a heap that still has moves, then a count
+pub fn random_move(game: &Game, random: u32) -> (u32, u32) {
+    let heaps: Vec<u32> = (0..game.heaps_remaining.len() as u32)
+        .filter(|heap| {
+            !game
+                .heap_moves(game.heaps_remaining[*heap as usize])
+                .is_empty()
+        })
+        .collect();
+    let heap = heaps[random as usize % heaps.len()];
+    let moves = game.heap_moves(game.heaps_remaining[heap as usize]);
+    (heap, moves[random as usize / heaps.len() % moves.len()])
+}
+
+/// Chance in percent that the program plays the optimal move at `difficulty`.
+/// `recent_results` are the player's latest games, `true` meaning the player won
+pub fn optimal_percent(difficulty: &DifficultyLevel, recent_results: &[bool]) -> u32 {
+    match difficulty {
+        DifficultyLevel::Easy => 0,
+        DifficultyLevel::Medium { optimal_percent } => (*optimal_percent as u32).min(100),
+        DifficultyLevel::Hard => 100,
+        DifficultyLevel::Adaptive => {
+            if recent_results.is_empty() {
+                return 50;
+            }
+            // The more the player wins, the sharper the program plays
+            let wins = recent_results.iter().filter(|won| **won).count() as u32;
+            10 + 80 * wins / recent_results.len() as u32
+        }
+    }
+}
+
+/// What a won wager against the program pays at `difficulty`, in percent of the
+/// stake; the stake itself included
+pub fn payout_percent(difficulty: &DifficultyLevel) -> u128 {
+    match difficulty {
+        DifficultyLevel::Easy => 150,
+        DifficultyLevel::Medium { optimal_percent } => 150 + (*optimal_percent).min(100) as u128,
+        DifficultyLevel::Hard => 300,
+        DifficultyLevel::Adaptive => 200,
+    }
+}
+
+/// Source of randomness for the strategies
+pub trait Rng {
+    fn next_u32(&mut self) -> u32;
+}
+
+/// Deterministic [`Rng`] (SplitMix64) for tests, replays and off-chain simulations
+#[derive(Debug, Clone)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+}
+
+impl Rng for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 32) as u32
+    }
+}
+
+/// How the program picks its moves
+pub trait Strategy {
+    /// The move to play as `(heap, pebbles)`. `grundy` is the table for custom
+    /// sets of moves, see [`optimal_move`]
+    fn choose(&self, game: &Game, grundy: &[u32], rng: &mut dyn Rng) -> (u32, u32);
+}
+
+/// Any legal move; `DifficultyLevel::Easy`
+pub struct RandomStrategy;
+
+impl Strategy for RandomStrategy {
+    fn choose(&self, game: &Game, _grundy: &[u32], rng: &mut dyn Rng) -> (u32, u32) {
+        random_move(game, rng.next_u32())
+    }
+}
+
+/// Always the optimal move; `DifficultyLevel::Hard`
+pub struct OptimalStrategy;
+
+impl Strategy for OptimalStrategy {
+    fn choose(&self, game: &Game, grundy: &[u32], _rng: &mut dyn Rng) -> (u32, u32) {
+        let (heap, pebbles, _) = optimal_move(game, grundy);
+        (heap, pebbles)
+    }
+}
+
+/// The optimal move with `optimal_percent`% probability, a random one otherwise;
+/// `DifficultyLevel::Medium` and `DifficultyLevel::Adaptive`
+pub struct MixedStrategy {
+    pub optimal_percent: u32,
+}
+
+impl Strategy for MixedStrategy {
+    fn choose(&self, game: &Game, grundy: &[u32], rng: &mut dyn Rng) -> (u32, u32) {
+        if rng.next_u32() % 100 < self.optimal_percent {
+            OptimalStrategy.choose(game, grundy, rng)
+        } else {
+            RandomStrategy.choose(game, grundy, rng)
+        }
+    }
+}
+
+impl DifficultyLevel {
+    /// The strategy playing at this level; `recent_results` as in [`optimal_percent`]
+    pub fn strategy(&self, recent_results: &[bool]) -> Box<dyn Strategy> {
+        match self {
+            DifficultyLevel::Easy => Box::new(RandomStrategy),
+            DifficultyLevel::Hard => Box::new(OptimalStrategy),
+            DifficultyLevel::Medium { .. } | DifficultyLevel::Adaptive => Box::new(MixedStrategy {
+                optimal_percent: optimal_percent(self, recent_results),
+            }),
+        }
+    }
+}
+
+/// How a finished game ended for the player
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Won,
+    Lost,
+    /// `GiveUp`, or a turn timeout
+    GaveUp,
+}
+
+/// A player's results against the program
+#[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+pub struct Stats {
+    pub wins: u32,
+    /// Games lost on the board; give-ups are counted separately
+    pub losses: u32,
+    pub give_ups: u32,
+    /// Wins in a row up to the latest game
+    pub current_streak: u32,
+    pub best_streak: u32,
+}
+
+impl Stats {
+    pub fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Won => {
+                self.wins += 1;
+                self.current_streak += 1;
+                self.best_streak = self.best_streak.max(self.current_streak);
+            }
+            Outcome::Lost => {
+                self.losses += 1;
+                self.current_streak = 0;
+            }
+            Outcome::GaveUp => {
+                self.give_ups += 1;
+                self.current_streak = 0;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+pub struct PlayerStats {
+    pub overall: Stats,
+    pub by_difficulty: BTreeMap<DifficultyLevel, Stats>,
+}
+
+impl PlayerStats {
+    pub fn record(&mut self, difficulty: &DifficultyLevel, outcome: Outcome) {
+        self.overall.record(outcome);
+        self.by_difficulty
+            .entry(difficulty.clone())
+            .or_default()
+            .record(outcome);
+    }
+}
+
+/// Elo rating of a player who has not finished a rated game yet
+pub const INITIAL_RATING: u32 = 1200;
+
+/// Largest rating change a single game can cause
+pub const RATING_K: i32 = 32;
+
+/// Expected score, in thousandths, of a player rated 0, 25, 50, ... 800 points
+/// above the opponent; the program cannot use floating point
+const EXPECTED_SCORE: [i32; 33] = [
+    500, 536, 571, 606, 640, 673, 703, 733, 760, 785, 808, 830, 849, 867, 882, 896, 909, 920, 930,
+    939, 947, 954, 960, 965, 969, 973, 977, 980, 983, 985, 987, 989, 990,
+];
+
+/// Fixed rating of the program playing at `difficulty`
+pub fn bot_rating(difficulty: &DifficultyLevel) -> u32 {
+    match difficulty {
+        DifficultyLevel::Easy => 800,
+        DifficultyLevel::Medium { optimal_percent } => {
+            1000 + 6 * (*optimal_percent).min(100) as u32
+        }
+        DifficultyLevel::Hard => 2000,
+        DifficultyLevel::Adaptive => INITIAL_RATING,
+    }
+}
+
+/// Elo change of a player rated `rating` after a game against `opponent`; the
+/// opponent's change is the same with the opposite sign
+pub fn rating_change(rating: u32, opponent: u32, won: bool) -> i32 {
+    let diff = (rating as i64 - opponent as i64).clamp(-800, 800) as i32;
+    let (index, rest) = ((diff.unsigned_abs() / 25) as usize, diff.abs() % 25);
+    let mut expected = EXPECTED_SCORE[index];
+    if rest > 0 {
+        expected += (EXPECTED_SCORE[index + 1] - expected) * rest / 25;
+    }
+    if diff < 0 {
+        expected = 1000 - expected;
+    }
+    let score = if won { 1000 } else { 0 };
+    let change = RATING_K * (score - expected);
+    // Rounds half away from zero, keeping both players' changes symmetric
+    (change + change.signum() * 500) / 1000
+}
+
+/// Number of rounds in a round robin between `players` players
+pub fn round_robin_rounds(players: u32) -> u32 {
+    (players + players % 2).saturating_sub(1)
+}
+
+/// Pairs of player indices meeting in `round` of a round robin, by the circle
+/// method; with an odd number of players one of them sits each round out
+pub fn round_robin_pairings(players: u32, round: u32) -> Vec<(u32, u32)> {
+    let slots = players + players % 2;
+    if slots < 2 {
+        return Vec::new();
+    }
+    let seat = |slot: u32| match slot {
+        0 => 0,
+        _ => 1 + (slot - 1 + round) % (slots - 1),
+    };
+    (0..slots / 2)
+        .map(|slot| (seat(slot), seat(slots - 1 - slot)))
+        .filter(|&(first, second)| first < players && second < players)
+        .collect()
+}
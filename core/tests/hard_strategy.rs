@@ -0,0 +1,260 @@
+//! `DifficultyLevel::Hard` must never lose a position that is won for it:
+//! exhaustively for small games, against random user lines for larger ones.
+
+use std::collections::HashMap;
+
+use pebbles_game_core::{
+    grundy_table, validate_params, Game, OptimalStrategy, PebblesInit, Player, RuleSet, SeededRng,
+    Strategy, WinCondition,
+};
+use proptest::{collection::vec, prelude::*};
+
+/// Positions are keyed by what decides the rest of the game
+type Memo = HashMap<(Vec<u32>, u32), bool>;
+
+fn new_game(params: PebblesInit) -> Game {
+    Game::new(params, Player::Program).expect("Invalid test parameters")
+}
+
+/// The table the program would use for `game`; classic rules need none
+fn grundy(game: &Game) -> Vec<u32> {
+    if game.allowed_moves.is_empty() {
+        Vec::new()
+    } else {
+        grundy_table(&game.allowed_moves, &game.win_condition, game.max_heap())
+    }
+}
+
+fn after(game: &Game, player: Player, (heap, pebbles): (u32, u32)) -> Game {
+    let mut next = game.clone();
+    next.apply_move(player, heap, pebbles, 0)
+        .expect("Illegal move");
+    next
+}
+
+/// Brute force, independent of the strategies: can the player to move force a win?
+fn mover_wins(game: &Game, mover: Player, memo: &mut Memo) -> bool {
+    let key = (game.heaps_remaining.clone(), game.current_max);
+    if let Some(wins) = memo.get(&key) {
+        return *wins;
+    }
+    let wins = game.legal_moves().into_iter().any(|turn| {
+        let next = after(game, mover.clone(), turn);
+        match next.winner() {
+            Some(winner) => *winner == mover,
+            None => !mover_wins(&next, next.next_player(), memo),
+        }
+    });
+    memo.insert(key, wins);
+    wins
+}
+
+/// With the program to move: does Hard win against every possible user reply?
+fn hard_always_wins(game: &Game, grundy: &[u32], memo: &mut Memo) -> bool {
+    let key = (game.heaps_remaining.clone(), game.current_max);
+    if let Some(wins) = memo.get(&key) {
+        return *wins;
+    }
+    let turn = OptimalStrategy.choose(game, grundy, &mut SeededRng::new(0));
+    let game = after(game, Player::Program, turn);
+    let wins = match game.winner() {
+        Some(winner) => *winner == Player::Program,
+        None => game.legal_moves().into_iter().all(|turn| {
+            let next = after(&game, Player::User, turn);
+            match next.winner() {
+                Some(winner) => *winner == Player::Program,
+                None => hard_always_wins(&next, grundy, memo),
+            }
+        }),
+    };
+    memo.insert(key, wins);
+    wins
+}
+
+/// Checks every valid, won starting position among `games`; returns how many there were
+fn check_exhaustively(games: impl IntoIterator<Item = PebblesInit>) -> usize {
+    let mut won = 0;
+    for params in games {
+        if validate_params(&params).is_err() {
+            continue;
+        }
+        let game = new_game(params.clone());
+        if !mover_wins(&game, Player::Program, &mut Memo::new()) {
+            continue;
+        }
+        won += 1;
+        assert!(
+            hard_always_wins(&game, &grundy(&game), &mut Memo::new()),
+            "Hard can lose {params:?}"
+        );
+    }
+    won
+}
+
+fn single_heap(count: u32, max: u32, win_condition: WinCondition) -> PebblesInit {
+    PebblesInit {
+        pebbles_count: count,
+        max_pebbles_per_turn: max,
+        win_condition,
+        ..Default::default()
+    }
+}
+
+fn pairs(limit: u32) -> impl Iterator<Item = (u32, u32)> {
+    (1..=limit).flat_map(move |count| (1..=count.min(8)).map(move |max| (count, max)))
+}
+
+#[test]
+fn classic_won_positions_match_the_formula() {
+    for (count, max) in pairs(40) {
+        let game = new_game(single_heap(count, max, WinCondition::Normal));
+        assert_eq!(
+            mover_wins(&game, Player::Program, &mut Memo::new()),
+            count % (max + 1) != 0,
+            "{count} pebbles, {max} per turn"
+        );
+    }
+}
+
+#[test]
+fn classic_exhaustive() {
+    let games = pairs(40).map(|(count, max)| single_heap(count, max, WinCondition::Normal));
+    assert!(check_exhaustively(games) > 0);
+}
+
+#[test]
+fn misere_exhaustive() {
+    let games = pairs(40).map(|(count, max)| single_heap(count, max, WinCondition::Misere));
+    assert!(check_exhaustively(games) > 0);
+}
+
+#[test]
+fn multi_heap_exhaustive() {
+    let mut games = Vec::new();
+    for max in 1..=3 {
+        for a in 1..=6 {
+            for b in 1..=a {
+                for c in 0..=b {
+                    let heaps = if c == 0 { vec![a, b] } else { vec![a, b, c] };
+                    games.push(PebblesInit {
+                        max_pebbles_per_turn: max,
+                        heaps,
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+    }
+    assert!(check_exhaustively(games) > 0);
+}
+
+#[test]
+fn allowed_moves_exhaustive() {
+    let mut games = Vec::new();
+    for allowed_moves in [vec![1, 3, 4], vec![2, 5], vec![1, 4], vec![3]] {
+        for win_condition in [WinCondition::Normal, WinCondition::Misere] {
+            for count in allowed_moves[0]..=25 {
+                games.push(PebblesInit {
+                    pebbles_count: count,
+                    win_condition: win_condition.clone(),
+                    allowed_moves: allowed_moves.clone(),
+                    ..Default::default()
+                });
+            }
+        }
+        for a in 1..=8 {
+            for b in 1..=a {
+                games.push(PebblesInit {
+                    heaps: vec![a, b],
+                    allowed_moves: allowed_moves.clone(),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    assert!(check_exhaustively(games) > 0);
+}
+
+#[test]
+fn fibonacci_exhaustive() {
+    let games = (2..=30).map(|count| PebblesInit {
+        pebbles_count: count,
+        rule_set: RuleSet::Fibonacci,
+        ..Default::default()
+    });
+    assert!(check_exhaustively(games) > 0);
+}
+
+/// Plays Hard against a user who picks each move from `choices`;
+/// returns the winner
+fn play(mut game: Game, choices: &[u32]) -> Player {
+    let grundy = grundy(&game);
+    let mut rng = SeededRng::new(0);
+    let mut choices = choices.iter().cycle();
+    loop {
+        let turn = OptimalStrategy.choose(&game, &grundy, &mut rng);
+        game = after(&game, Player::Program, turn);
+        if let Some(winner) = game.winner() {
+            return winner.clone();
+        }
+        let moves = game.legal_moves();
+        let choice = *choices.next().unwrap_or(&0) as usize % moves.len();
+        game = after(&game, Player::User, moves[choice]);
+        if let Some(winner) = game.winner() {
+            return winner.clone();
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn classic_hard_wins_against_any_line(
+        mut count in 1u32..5_000,
+        max in 1u32..100,
+        choices in vec(any::<u32>(), 1..64),
+    ) {
+        if count % (max + 1) == 0 {
+            // Lost for the mover; one more pebble makes it won
+            count += 1;
+        }
+        prop_assume!(max <= count);
+        let game = new_game(single_heap(count, max, WinCondition::Normal));
+        prop_assert_eq!(play(game, &choices), Player::Program);
+    }
+
+    #[test]
+    fn misere_hard_wins_against_any_line(
+        mut count in 1u32..5_000,
+        max in 1u32..100,
+        choices in vec(any::<u32>(), 1..64),
+    ) {
+        if count % (max + 1) == 1 {
+            // Lost for the mover; one more pebble makes it won
+            count += 1;
+        }
+        prop_assume!(max <= count);
+        let game = new_game(single_heap(count, max, WinCondition::Misere));
+        prop_assert_eq!(play(game, &choices), Player::Program);
+    }
+
+    #[test]
+    fn multi_heap_hard_wins_against_any_line(
+        mut heaps in vec(1u32..200, 2..5),
+        max in 1u32..20,
+        choices in vec(any::<u32>(), 1..64),
+    ) {
+        let nim_sum = heaps.iter().fold(0, |sum, heap| sum ^ (heap % (max + 1)));
+        if nim_sum == 0 {
+            // The position is lost for the mover; changing any one heap makes it won
+            heaps[0] += 1;
+        }
+        let params = PebblesInit {
+            max_pebbles_per_turn: max,
+            heaps,
+            ..Default::default()
+        };
+        prop_assume!(validate_params(&params).is_ok());
+        let game = new_game(params);
+        prop_assert_eq!(play(game, &choices), Player::Program);
+    }
+}
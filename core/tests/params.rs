@@ -0,0 +1,67 @@
+use pebbles_game_core::{
+    validate_params, Game, PebblesError, PebblesInit, Player, RuleSet, WinCondition,
+    MAX_ALLOWED_MOVES,
+};
+
+/// `Game::params` gives back the starting position after any number of moves
+#[test]
+fn params_restart_the_game() {
+    let cases = [
+        PebblesInit {
+            pebbles_count: 15,
+            max_pebbles_per_turn: 4,
+            ..Default::default()
+        },
+        PebblesInit {
+            pebbles_count: 15,
+            max_pebbles_per_turn: 4,
+            win_condition: WinCondition::Misere,
+            ..Default::default()
+        },
+        PebblesInit {
+            max_pebbles_per_turn: 3,
+            heaps: vec![3, 7, 5],
+            ..Default::default()
+        },
+        PebblesInit {
+            heaps: vec![9, 4],
+            allowed_moves: vec![4, 1, 3],
+            ..Default::default()
+        },
+        PebblesInit {
+            pebbles_count: 20,
+            rule_set: RuleSet::Fibonacci,
+            ..Default::default()
+        },
+    ];
+    for params in cases {
+        let start = Game::new(params, Player::User).unwrap();
+        let mut game = start.clone();
+        let mut player = Player::User;
+        while let Some(&(heap, pebbles)) = game.legal_moves().last() {
+            game.apply_move(player.clone(), heap, pebbles, 0).unwrap();
+            player = game.opponent_of(&player);
+
+            let again = Game::new(game.params(), Player::User).unwrap();
+            assert_eq!(again.heaps_remaining, start.heaps_remaining);
+            assert_eq!(again.allowed_moves, start.allowed_moves);
+            assert_eq!(again.current_max, start.current_max);
+            assert_eq!(again.win_condition, start.win_condition);
+        }
+    }
+}
+
+/// Move sets are capped so a single Grundy table stays cheap to compute
+#[test]
+fn allowed_moves_are_capped() {
+    let params = |moves: u32| PebblesInit {
+        heaps: vec![100],
+        allowed_moves: (1..=moves).collect(),
+        ..Default::default()
+    };
+    assert_eq!(validate_params(&params(MAX_ALLOWED_MOVES as u32)), Ok(()));
+    assert_eq!(
+        validate_params(&params(MAX_ALLOWED_MOVES as u32 + 1)),
+        Err(PebblesError::InvalidAllowedMoves)
+    );
+}
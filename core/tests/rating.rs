@@ -0,0 +1,38 @@
+use pebbles_game_core::{bot_rating, rating_change, DifficultyLevel, INITIAL_RATING, RATING_K};
+
+#[test]
+fn equal_ratings_split_k() {
+    assert_eq!(rating_change(1500, 1500, true), RATING_K / 2);
+    assert_eq!(rating_change(1500, 1500, false), -RATING_K / 2);
+}
+
+#[test]
+fn changes_are_symmetric_and_bounded() {
+    for rating in (0..3000).step_by(37) {
+        for opponent in (0..3000).step_by(41) {
+            for won in [true, false] {
+                let change = rating_change(rating, opponent, won);
+                assert_eq!(change, -rating_change(opponent, rating, !won));
+                assert!(change.abs() <= RATING_K);
+                // A win never costs rating and a loss never gains any
+                assert!(if won { change >= 0 } else { change <= 0 });
+            }
+        }
+    }
+}
+
+#[test]
+fn upsets_move_ratings_more() {
+    let hard = bot_rating(&DifficultyLevel::Hard);
+    let easy = bot_rating(&DifficultyLevel::Easy);
+    assert!(rating_change(INITIAL_RATING, hard, true) > rating_change(INITIAL_RATING, easy, true));
+    assert!(
+        rating_change(INITIAL_RATING, easy, false) < rating_change(INITIAL_RATING, hard, false)
+    );
+    assert!(easy < bot_rating(&DifficultyLevel::Medium { optimal_percent: 0 }));
+    assert!(
+        bot_rating(&DifficultyLevel::Medium {
+            optimal_percent: 100
+        }) < hard
+    );
+}
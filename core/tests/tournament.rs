@@ -0,0 +1,21 @@
+use std::collections::BTreeSet;
+
+use pebbles_game_core::{round_robin_pairings, round_robin_rounds};
+
+#[test]
+fn round_robin_pairs_everyone_once() {
+    for players in 0..12 {
+        let mut met = BTreeSet::new();
+        for round in 0..round_robin_rounds(players) {
+            let mut seated = BTreeSet::new();
+            for (first, second) in round_robin_pairings(players, round) {
+                assert_ne!(first, second);
+                // Nobody plays twice in a round or meets the same player twice
+                assert!(seated.insert(first) && seated.insert(second));
+                assert!(met.insert((first.min(second), first.max(second))));
+            }
+            assert!(seated.len() as u32 >= players - players % 2);
+        }
+        assert_eq!(met.len() as u32, players * players.saturating_sub(1) / 2);
+    }
+}
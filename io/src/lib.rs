@@ -1,64 +1,376 @@
 #![no_std]
 
-use gmeta::{In, InOut, Out, Metadata};
-use gstd::{Decode, Encode, TypeInfo};
+use gmeta::{In, InOut, Metadata};
+use gstd::{prelude::*, ActorId, Decode, Encode, TypeInfo};
+
+pub use pebbles_game_core::{
+    bot_rating, payout_percent, rating_change, DifficultyLevel, Game as GameState, Move,
+    PebblesError, PebblesInit, Player, PlayerStats, RuleSet, Stats, WinCondition, INITIAL_RATING,
+    MAX_ALLOWED_MOVES_HEAP,
+};
+
+/// Identifies a game against the program; an account may play several at once
+pub type GameId = u64;
+
+/// A best-of-N series against the program, identified by the id of its first game
+pub type SeriesId = GameId;
+
+/// Number of moves returned by one `StateQuery::History` page
+pub const HISTORY_PAGE_SIZE: usize = 20;
+
+/// Blocks a lobby entry waits for a match before it expires
+pub const QUEUE_TTL: u32 = 100;
+
+pub type TournamentId = u64;
+
+/// Keeps a tournament round within the gas of a single message
+pub const MAX_TOURNAMENT_PLAYERS: usize = 64;
+
+/// Longest registration period, about a week of 3-second blocks; the program
+/// reserves gas for the whole period to start the tournament on time
+pub const MAX_REGISTRATION_BLOCKS: u32 = 201_600;
 
 pub struct PebblesMetadata;
 
 impl Metadata for PebblesMetadata {
     type Init = In<PebblesInit>;
-    type Handle = InOut<PebblesAction, PebblesEvent>;
-    type State = Out<GameState>;
+    type Handle = InOut<PebblesAction, Result<PebblesEvent, PebblesError>>;
+    type State = InOut<StateQuery, StateReply>;
     type Reply = ();
     type Others = ();
     type Signal = ();
 }
 
-#[derive(Debug, Default, Clone, Encode, Decode, TypeInfo)]
-pub struct PebblesInit {
-    pub difficulty: DifficultyLevel,
-    pub pebbles_count: u32,
-    pub max_pebbles_per_turn: u32,
-}
-
-#[derive(Debug, Default, Clone, Encode, Decode, TypeInfo)]
-pub enum DifficultyLevel {
-    #[default]
-    Easy,
-    Hard,
-}
-
 #[derive(Debug, Clone, Encode, Decode, TypeInfo)]
 pub enum PebblesAction {
-    Turn(u32),
-    GiveUp,
+    /// Starts a new game for `msg::source()` with these parameters, or with those
+    /// from `PebblesInit` when `None`. Attached value is a wager the program pays
+    /// out per `payout_percent` on a win
+    StartGame(Option<PebblesInit>),
+    /// Takes pebbles from heap 0
+    Turn {
+        id: GameId,
+        count: u32,
+    },
+    TurnOnHeap {
+        id: GameId,
+        heap: u32,
+        count: u32,
+    },
+    GiveUp(GameId),
+    /// Asks for the optimal move in the current position
+    Hint(GameId),
+    /// Starts a new game with the parameters of the finished game, the other
+    /// player moving first. Attached value is a wager as in `StartGame`
+    Rematch(GameId),
+    /// Starts a best-of-N series with these parameters, or with those from
+    /// `PebblesInit` when `None`. Each game starts as soon as the previous one
+    /// ends, with the other player moving first; series games are not wagered
+    StartSeries {
+        best_of: u32,
+        params: Option<PebblesInit>,
+    },
+    /// Replaces the game with a new one under the same id; an unfinished game
+    /// counts as given up
     Restart {
+        id: GameId,
         difficulty: DifficultyLevel,
         pebbles_count: u32,
         max_pebbles_per_turn: u32,
+        win_condition: WinCondition,
+        heaps: Vec<u32>,
+        allowed_moves: Vec<u32>,
+        rule_set: RuleSet,
+    },
+    /// Offers a PvP game with these parameters to any other account; attached
+    /// value is the stake both players put in escrow
+    Challenge(PebblesInit),
+    /// Withdraws the sender's challenge and refunds its stake
+    CancelChallenge,
+    /// Accepts the open challenge of the given account and starts the duel;
+    /// must carry the challenger's stake
+    AcceptChallenge(ActorId),
+    /// A turn in the sender's duel
+    DuelTurn {
+        heap: u32,
+        count: u32,
+    },
+    DuelGiveUp,
+    /// Ends a game against the program if the turn deadline from
+    /// `PebblesInit::turn_timeout` has passed. The program sends it to itself
+    /// after every turn; anyone else may send it too
+    CheckTimeout(GameId),
+    /// Like `CheckTimeout`, for the account's duel; the player to move loses
+    CheckDuelTimeout(ActorId),
+    /// Enters the matchmaking lobby. The sender is paired with the longest waiting
+    /// account whose game parameters are the same and whose rating differs from
+    /// theirs by at most both `rating_range`s; the matched duel has no stake
+    JoinQueue {
+        rating_range: u32,
+        pebbles_count: u32,
+        max_pebbles_per_turn: u32,
+    },
+    LeaveQueue,
+    /// Removes the account's lobby entry once `QUEUE_TTL` blocks have passed.
+    /// The program sends it to itself on `JoinQueue`; anyone else may send it too
+    CheckQueueExpiry(ActorId),
+    /// Announces a tournament whose matches are played with `params`
+    CreateTournament {
+        format: TournamentFormat,
+        params: PebblesInit,
+        entry_fee: u128,
+        /// Block height at which registration closes and the tournament starts
+        registration_deadline: u32,
     },
+    /// Must carry the entry fee, which goes into the prize pool
+    RegisterForTournament(TournamentId),
+    /// Seeds the bracket and starts the first round once the registration
+    /// deadline has passed; with fewer than two players the tournament is
+    /// cancelled and the fees are refunded. The program sends it to itself at
+    /// the deadline; anyone else may send it too
+    StartTournament(TournamentId),
+    /// A turn in the sender's match of the current round
+    TournamentTurn {
+        id: TournamentId,
+        heap: u32,
+        count: u32,
+    },
+    TournamentGiveUp(TournamentId),
+    /// Like `CheckDuelTimeout`, for every match of the tournament's current round
+    CheckTournamentTimeout(TournamentId),
 }
 
 #[derive(Debug, Clone, Encode, Decode, TypeInfo)]
 pub enum PebblesEvent {
+    /// Reply to `StartGame`, `Rematch` and `StartSeries`, also sent when the next
+    /// game of a series starts; when the program moves first, `remaining` is
+    /// counted after its opening turn
+    GameStarted {
+        id: GameId,
+        first_player: Player,
+        remaining: u32,
+    },
+    /// Reply to `Restart`, like `GameStarted`; `rating_change` is set when the
+    /// replaced game was unfinished and counted as given up
+    Restarted {
+        id: GameId,
+        first_player: Player,
+        remaining: u32,
+        rating_change: Option<i32>,
+    },
     CounterTurn(u32),
-    Won(Player),
+    /// The program's turn in a multi-heap game
+    CounterTurnOnHeap {
+        heap: u32,
+        pebbles: u32,
+    },
+    /// `rating_change` is the user's, against the fixed `bot_rating` of the difficulty
+    Won {
+        winner: Player,
+        rating_change: i32,
+    },
+    /// `winning` is false when every move loses against perfect play;
+    /// `suggested` is then a delaying move
+    Hint {
+        heap: u32,
+        suggested: u32,
+        winning: bool,
+    },
+    /// Sent to the player; `score` is the user's wins and then the program's
+    SeriesWon {
+        series: SeriesId,
+        winner: Player,
+        score: (u32, u32),
+    },
+    ChallengeCreated,
+    ChallengeCancelled,
+    /// Sent to both players
+    DuelStarted {
+        challenger: ActorId,
+        opponent: ActorId,
+        first_player: ActorId,
+    },
+    /// A turn in a duel, sent to both players
+    DuelMove {
+        player: ActorId,
+        heap: u32,
+        pebbles: u32,
+    },
+    /// Sent to both players; the winner takes both stakes and gains `rating_change`,
+    /// the loser loses as much
+    DuelWon {
+        winner: ActorId,
+        rating_change: i32,
+    },
+    /// Carries a won wager or a refunded stake as the message value
+    Payout(u128),
+    /// Reply to `JoinQueue` when no compatible account is waiting
+    Queued,
+    /// Sent to both players when the lobby pairs them; the duel then proceeds as
+    /// if `waiting` had challenged `joined`
+    Matched {
+        waiting: ActorId,
+        joined: ActorId,
+        first_player: ActorId,
+    },
+    /// Reply to `LeaveQueue`, also sent to the account when its entry expires
+    QueueLeft,
+    TournamentCreated(TournamentId),
+    Registered(TournamentId),
+    TournamentStarted(TournamentId),
+    /// Sent to both players of a match; `players.0` plays as `Player::User`
+    MatchStarted {
+        tournament: TournamentId,
+        round: u32,
+        players: (ActorId, ActorId),
+        first_player: ActorId,
+    },
+    /// A turn in a tournament match, sent to both players
+    MatchMove {
+        tournament: TournamentId,
+        player: ActorId,
+        heap: u32,
+        pebbles: u32,
+    },
+    /// Sent to both players; ratings change as in `DuelWon`
+    MatchWon {
+        tournament: TournamentId,
+        winner: ActorId,
+        rating_change: i32,
+    },
+    /// Sent to every player; the pool is split evenly between the `winners`,
+    /// the first of them also receiving what does not divide
+    TournamentWon {
+        tournament: TournamentId,
+        winners: Vec<ActorId>,
+        prize: u128,
+    },
+    /// Sent to every registered player, whose fees are refunded
+    TournamentCancelled(TournamentId),
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct Series {
+    pub player: ActorId,
+    pub best_of: u32,
+    pub params: PebblesInit,
+    /// The user's wins and then the program's
+    pub score: (u32, u32),
+    pub winner: Option<Player>,
 }
 
-#[derive(Debug, Default, Clone, Encode, Decode, TypeInfo)]
-pub enum Player {
-    #[default]
-    User,
-    Program,
+/// An open PvP challenge
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct Challenge {
+    pub params: PebblesInit,
+    pub stake: u128,
 }
 
-#[derive(Debug, Default, Clone, Encode, Decode, TypeInfo)]
-pub struct GameState {
+/// An account waiting in the matchmaking lobby
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct QueueEntry {
+    pub rating_range: u32,
     pub pebbles_count: u32,
     pub max_pebbles_per_turn: u32,
-    pub pebbles_remaining: u32,
-    pub difficulty: DifficultyLevel,
-    pub first_player: Player,
-    pub winner: Option<Player>,
+    pub joined_at: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+pub enum TournamentFormat {
+    /// Losers drop out; with an odd number of players left the last one in the
+    /// bracket skips the round
+    SingleElimination,
+    /// Everyone meets everyone once; the players with the most wins share the pool
+    RoundRobin,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+pub enum TournamentStage {
+    Registration,
+    Running,
+    Finished,
+    Cancelled,
+}
+
+/// A PvP game of a tournament round
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct TournamentMatch {
+    pub players: (ActorId, ActorId),
+    pub game: GameState,
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct Tournament {
+    pub organiser: ActorId,
+    pub format: TournamentFormat,
+    pub params: PebblesInit,
+    pub entry_fee: u128,
+    pub registration_deadline: u32,
+    pub stage: TournamentStage,
+    /// In registration order, then shuffled into bracket order at the start
+    pub players: Vec<ActorId>,
+    /// Match wins of each of `players`
+    pub wins: Vec<u32>,
+    /// Players not yet eliminated, in bracket order
+    pub alive: Vec<ActorId>,
+    pub round: u32,
+    /// Matches of the current round
+    pub matches: Vec<TournamentMatch>,
+    pub winners: Vec<ActorId>,
+}
+
+/// A PvP game: the challenger plays as `Player::User`, the account that accepted
+/// the challenge as `Player::Opponent`
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct Duel {
+    pub challenger: ActorId,
+    pub opponent: ActorId,
+    pub game: GameState,
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub enum StateQuery {
+    Game(GameId),
+    Winner(GameId),
+    /// Moves the player may make on the next turn
+    LegalMoves(GameId),
+    /// `HISTORY_PAGE_SIZE` moves of the game, starting from page 0
+    History(GameId, u32),
+    /// Ids of the account's unfinished games against the program
+    ActiveGames(ActorId),
+    Series(SeriesId),
+    /// Up to N players with the most wins against the program
+    Leaderboard(u32),
+    PlayerStats(ActorId),
+    /// Elo rating of an account, `INITIAL_RATING` before its first rated game
+    Rating(ActorId),
+    /// Default parameters used by `StartGame`
+    Config,
+    /// Open PvP challenges
+    Challenges,
+    /// The latest duel of a player
+    Duel(ActorId),
+    /// Accounts waiting in the matchmaking lobby
+    Queue,
+    Tournament(TournamentId),
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub enum StateReply {
+    Game(Option<GameState>),
+    Winner(Option<Player>),
+    /// `(heap, pebbles)` pairs
+    LegalMoves(Vec<(u32, u32)>),
+    History(Vec<Move>),
+    Config(PebblesInit),
+    ActiveGames(Vec<GameId>),
+    Series(Option<Series>),
+    /// Sorted by wins, then best streak, then fewest losses and give-ups
+    Leaderboard(Vec<(ActorId, Stats)>),
+    PlayerStats(Option<PlayerStats>),
+    Rating(u32),
+    Challenges(Vec<(ActorId, Challenge)>),
+    Duel(Option<Duel>),
+    Queue(Vec<(ActorId, QueueEntry)>),
+    Tournament(Option<Tournament>),
+}
@@ -1,20 +1,1478 @@
 #![no_std]
 
+use core::{
+    cmp::Reverse,
+    ptr::{addr_of, addr_of_mut},
+};
+use gstd::{collections::BTreeMap, exec, msg, prelude::*, ActorId, ReservationId};
+use pebbles_game_core::{
+    grundy_table, optimal_move, round_robin_pairings, round_robin_rounds, validate_params, Outcome,
+    Rng, SeededRng, Strategy,
+};
 use pebbles_game_io::*;
 
-static mut PEBBLES_GAME: Option<GameState> = None;
+/// Adaptive 难度参考的最近对局数
+const RECENT_RESULTS_LEN: usize = 10;
+
+/// 超时检查消息预留的 gas
+const TIMEOUT_GAS: u64 = 5_000_000_000;
+
+/// 自定义走法集合的 Grundy 表缓存，按 (胜负规则, 走法集合) 索引
+type GrundyTables = BTreeMap<(WinCondition, Vec<u32>), Vec<u32>>;
+
+/// 最多缓存的 Grundy 表数量
+const MAX_GRUNDY_TABLES: usize = 16;
+
+/// 对程序的一局及下这局的玩家
+struct Session {
+    player: ActorId,
+    game: GameState,
+    /// 这局所属的系列赛
+    series: Option<SeriesId>,
+}
+
+struct Pebbles {
+    /// 新会话使用的默认参数
+    config: PebblesInit,
+    games: BTreeMap<GameId, Session>,
+    /// 玩家 -> 他的对局；已结束的留到他下次开局时才删除，方便查询
+    player_games: BTreeMap<ActorId, Vec<GameId>>,
+    next_game_id: GameId,
+    series: BTreeMap<SeriesId, Series>,
+    /// 每个玩家最近几局的胜负，true 表示玩家获胜
+    recent_results: BTreeMap<ActorId, Vec<bool>>,
+    /// 每个玩家对程序的战绩
+    stats: BTreeMap<ActorId, PlayerStats>,
+    /// Elo 等级分，没有的按 INITIAL_RATING 算
+    ratings: BTreeMap<ActorId, u32>,
+    grundy_tables: GrundyTables,
+    /// 所有消息共用的随机数，PebblesInit::seed 设置后可复现
+    rng: ProgramRng,
+    /// 等待应战的 PvP 挑战，按发起者索引
+    challenges: BTreeMap<ActorId, Challenge>,
+    /// PvP 对局，按发起挑战的一方索引
+    duels: BTreeMap<ActorId, Duel>,
+    /// 玩家 -> 他最近一场对局在 duels 中的键
+    duel_of: BTreeMap<ActorId, ActorId>,
+    /// 匹配大厅中等待对手的玩家
+    queue: BTreeMap<ActorId, QueueEntry>,
+    tournaments: BTreeMap<TournamentId, Tournament>,
+    next_tournament_id: TournamentId,
+    /// 托管中、随时可能要付出的金额：未结束对局的最高赔付、PvP 双方的赌注和比赛奖池
+    escrow: u128,
+}
+
+impl Pebbles {
+    /// 程序在该难度下对这个玩家使用的策略
+    fn strategy(&self, player: &ActorId, difficulty: &DifficultyLevel) -> Box<dyn Strategy> {
+        let recent = self
+            .recent_results
+            .get(player)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        difficulty.strategy(recent)
+    }
+
+    fn record_result(&mut self, player: ActorId, user_won: bool) {
+        let recent = self.recent_results.entry(player).or_default();
+        recent.push(user_won);
+        if recent.len() > RECENT_RESULTS_LEN {
+            recent.remove(0);
+        }
+    }
+
+    /// 删除玩家所有已结束的对局
+    fn forget_finished_games(&mut self, player: &ActorId) {
+        let Some(ids) = self.player_games.get_mut(player) else {
+            return;
+        };
+        let games = &mut self.games;
+        ids.retain(|id| {
+            let finished = games[id].game.winner.is_some();
+            if finished {
+                games.remove(id);
+            }
+            !finished
+        });
+    }
+
+    fn duel_in_progress(&self, player: &ActorId) -> bool {
+        self.duel_of
+            .get(player)
+            .and_then(|challenger| self.duels.get(challenger))
+            .is_some_and(|duel| duel.game.winner.is_none())
+    }
+
+    fn duel_mut(&mut self, player: &ActorId) -> Result<&mut Duel, PebblesError> {
+        let challenger = self.duel_of.get(player).ok_or(PebblesError::NoActiveGame)?;
+        Ok(self
+            .duels
+            .get_mut(challenger)
+            .expect("Pebbles::duel_mut(): the duel index is out of sync"))
+    }
+
+    /// 押注 stake 对程序开局：程序余额要能付得起这局可能的赔付
+    fn reserve_payout(
+        &mut self,
+        stake: u128,
+        difficulty: &DifficultyLevel,
+    ) -> Result<(), PebblesError> {
+        self.escrow += self.covered_payout(stake, difficulty)?;
+        Ok(())
+    }
+
+    /// 确认余额还能兑付这笔赌注赢了时的赔付，返回赔付金额
+    fn covered_payout(
+        &self,
+        stake: u128,
+        difficulty: &DifficultyLevel,
+    ) -> Result<u128, PebblesError> {
+        let payout = stake * payout_percent(difficulty) / 100;
+        if exec::value_available() < self.escrow + payout {
+            return Err(PebblesError::InsufficientBankroll);
+        }
+        Ok(payout)
+    }
+
+    fn rating(&self, player: &ActorId) -> u32 {
+        self.ratings.get(player).copied().unwrap_or(INITIAL_RATING)
+    }
+
+    fn update_rating(&mut self, player: ActorId, change: i32) {
+        let rating = self.rating(&player).saturating_add_signed(change);
+        self.ratings.insert(player, rating);
+    }
+
+    /// 对程序的一局结束：记下胜负、战绩和等级分，玩家赢了就付给他赔付；
+    /// 返回玩家的等级分变化
+    fn finish_game(&mut self, id: GameId, gave_up: bool) -> i32 {
+        let Session { player, game, .. } = &self.games[&id];
+        let (player, user_won) = (*player, game.winner == Some(Player::User));
+        let payout = game.stake * payout_percent(&game.difficulty) / 100;
+        let change = rating_change(self.rating(&player), bot_rating(&game.difficulty), user_won);
+        let outcome = match (user_won, gave_up) {
+            (true, _) => Outcome::Won,
+            (false, true) => Outcome::GaveUp,
+            (false, false) => Outcome::Lost,
+        };
+        self.stats
+            .entry(player)
+            .or_default()
+            .record(&game.difficulty, outcome);
+        self.record_result(player, user_won);
+        self.update_rating(player, change);
+        self.escrow -= payout;
+        if user_won {
+            pay(player, payout);
+        }
+        change
+    }
+
+    /// 撤回玩家的挑战并退还赌注
+    fn withdraw_challenge(&mut self, player: &ActorId) -> Option<Challenge> {
+        let challenge = self.challenges.remove(player)?;
+        self.escrow -= challenge.stake;
+        pay(*player, challenge.stake);
+        Some(challenge)
+    }
+
+    /// 删除玩家已结束的对局，对手的索引一并清理
+    fn forget_duel(&mut self, player: &ActorId) {
+        let Some(challenger) = self.duel_of.remove(player) else {
+            return;
+        };
+        if let Some(duel) = self.duels.remove(&challenger) {
+            for account in [duel.challenger, duel.opponent] {
+                if self.duel_of.get(&account) == Some(&challenger) {
+                    self.duel_of.remove(&account);
+                }
+            }
+        }
+    }
+}
+
+static mut PEBBLES: Option<Pebbles> = None;
+
+/// 程序使用的随机数：默认来自 exec::random，设置了种子时改用可复现的序列
+enum ProgramRng {
+    /// salt 由消息 id 和取数次数组成；次数保存在状态里，同一条消息里的每次
+    /// 取数（包括不同函数里的）都换一个 salt
+    Exec {
+        counter: u32,
+    },
+    Seeded(SeededRng),
+}
+
+impl Rng for ProgramRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            ProgramRng::Exec { counter } => {
+                let mut salt: [u8; 32] = msg::id().into();
+                for (byte, count) in salt.iter_mut().zip(counter.to_le_bytes()) {
+                    *byte ^= count;
+                }
+                *counter = counter.wrapping_add(1);
+                let (hash, _num) =
+                    exec::random(salt).expect("ProgramRng::next_u32(): random call failed");
+                u32::from_le_bytes([hash[0], hash[1], hash[2], hash[3]])
+            }
+            ProgramRng::Seeded(rng) => rng.next_u32(),
+        }
+    }
+}
+
+/// 取出（必要时先算好）该局使用的 Grundy 表；经典规则不需要表
+fn cached_grundy_table<'a>(tables: &'a mut GrundyTables, game: &GameState) -> &'a [u32] {
+    if game.allowed_moves.is_empty() {
+        return &[];
+    }
+    let max_heap = game.max_heap();
+    let key = (game.win_condition.clone(), game.allowed_moves.clone());
+    if !tables.contains_key(&key) && tables.len() >= MAX_GRUNDY_TABLES {
+        // 缓存满了先丢掉一张，以后用到时再重新计算
+        tables.pop_first();
+    }
+    let table = tables.entry(key).or_default();
+    if table.len() <= max_heap as usize {
+        *table = grundy_table(&game.allowed_moves, &game.win_condition, max_heap);
+    }
+    table
+}
+
+/// 新开一局，没有指定先手时随机决定；如果程序先手，它的第一步已经走好
+fn start_game(
+    params: PebblesInit,
+    first_player: Option<Player>,
+    grundy_tables: &mut GrundyTables,
+    strategy: &dyn Strategy,
+    rng: &mut dyn Rng,
+) -> Result<GameState, PebblesError> {
+    let first_player = first_player.unwrap_or_else(|| match rng.next_u32() % 2 {
+        0 => Player::User,
+        _ => Player::Program,
+    });
+    let mut game = GameState::new(params, first_player.clone())?;
+
+    if let Player::Program = first_player {
+        let grundy = cached_grundy_table(grundy_tables, &game);
+        let (heap, taken) = strategy.choose(&game, grundy, rng);
+        game.apply_move(Player::Program, heap, taken, exec::block_height())
+            .expect("The program chose an illegal move");
+    }
+
+    Ok(game)
+}
 
 #[no_mangle]
-pub extern "C" fn init() {
-    // 我的代码
+extern "C" fn init() {
+    let config: PebblesInit = msg::load().expect("Unable to decode PebblesInit");
+    validate_params(&config).unwrap_or_else(|e| panic!("Invalid PebblesInit: {e:?}"));
+    unsafe {
+        PEBBLES = Some(Pebbles {
+            games: BTreeMap::new(),
+            player_games: BTreeMap::new(),
+            next_game_id: 0,
+            series: BTreeMap::new(),
+            recent_results: BTreeMap::new(),
+            stats: BTreeMap::new(),
+            ratings: BTreeMap::new(),
+            grundy_tables: BTreeMap::new(),
+            rng: match config.seed {
+                Some(seed) => ProgramRng::Seeded(SeededRng::new(seed)),
+                None => ProgramRng::Exec { counter: 0 },
+            },
+            challenges: BTreeMap::new(),
+            duels: BTreeMap::new(),
+            duel_of: BTreeMap::new(),
+            queue: BTreeMap::new(),
+            tournaments: BTreeMap::new(),
+            next_tournament_id: 0,
+            // 初始化时附带的金额就是程序的资金池
+            escrow: 0,
+            config,
+        })
+    };
+}
+
+/// 程序走棋后的回复；单堆游戏沿用 CounterTurn
+fn counter_turn_event(game: &GameState, heap: u32, pebbles: u32) -> PebblesEvent {
+    if game.heaps_remaining.len() == 1 {
+        PebblesEvent::CounterTurn(pebbles)
+    } else {
+        PebblesEvent::CounterTurnOnHeap { heap, pebbles }
+    }
+}
+
+fn user_turn(
+    game: &mut GameState,
+    grundy_tables: &mut GrundyTables,
+    heap: u32,
+    pebbles: u32,
+    strategy: &dyn Strategy,
+    rng: &mut dyn Rng,
+) -> Result<PebblesEvent, PebblesError> {
+    game.apply_move(Player::User, heap, pebbles, exec::block_height())?;
+    if let Some(winner) = &game.winner {
+        return Ok(won_event(winner));
+    }
+
+    let grundy = cached_grundy_table(grundy_tables, game);
+    let (heap, taken) = strategy.choose(game, grundy, rng);
+    game.apply_move(Player::Program, heap, taken, exec::block_height())
+        .expect("The program chose an illegal move");
+    match &game.winner {
+        Some(winner) => Ok(won_event(winner)),
+        None => Ok(counter_turn_event(game, heap, taken)),
+    }
+}
+
+/// 等级分变化在 finish_game 之后填入
+fn won_event(winner: &Player) -> PebblesEvent {
+    PebblesEvent::Won {
+        winner: winner.clone(),
+        rating_change: 0,
+    }
+}
+
+/// 玩家认输（或超时），程序获胜
+fn give_up(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    id: GameId,
+) -> Result<PebblesEvent, PebblesError> {
+    player_game(&mut pebbles.games, &player, id)?.give_up(&Player::User)?;
+    Ok(PebblesEvent::Won {
+        winner: Player::Program,
+        rating_change: end_game(pebbles, id, true),
+    })
+}
+
+fn hint(game: &GameState, grundy_tables: &mut GrundyTables) -> Result<PebblesEvent, PebblesError> {
+    if game.winner.is_some() {
+        return Err(PebblesError::GameOver);
+    }
+    let grundy = cached_grundy_table(grundy_tables, game);
+    let (heap, suggested, winning) = optimal_move(game, grundy);
+    Ok(PebblesEvent::Hint {
+        heap,
+        suggested,
+        winning,
+    })
+}
+
+/// 附带的金额会被当作赌注收下的操作；其余操作的金额随回复退回
+fn takes_stake(action: &PebblesAction) -> bool {
+    matches!(
+        action,
+        PebblesAction::StartGame(_)
+            | PebblesAction::Rematch(_)
+            | PebblesAction::Restart { .. }
+            | PebblesAction::Challenge(_)
+            | PebblesAction::AcceptChallenge(_)
+            | PebblesAction::RegisterForTournament(_)
+    )
+}
+
+fn process_action(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    action: PebblesAction,
+    value: u128,
+) -> Result<PebblesEvent, PebblesError> {
+    match action {
+        PebblesAction::StartGame(params) => {
+            pebbles.forget_finished_games(&player);
+            let params = params.unwrap_or_else(|| pebbles.config.clone());
+            open_session(pebbles, player, params, value, None, None)
+        }
+        PebblesAction::Rematch(id) => {
+            let game = player_game(&mut pebbles.games, &player, id)?;
+            if game.winner.is_none() {
+                return Err(PebblesError::GameInProgress);
+            }
+            let first_player = game.opponent_of(&game.first_player);
+            let params = game.params();
+            open_session(pebbles, player, params, value, Some(first_player), None)
+        }
+        PebblesAction::StartSeries { best_of, params } => {
+            let params = params.unwrap_or_else(|| pebbles.config.clone());
+            start_series(pebbles, player, best_of, params)
+        }
+        PebblesAction::Turn { id, count } => turn(pebbles, player, id, 0, count),
+        PebblesAction::TurnOnHeap { id, heap, count } => turn(pebbles, player, id, heap, count),
+        PebblesAction::GiveUp(id) => give_up(pebbles, player, id),
+        PebblesAction::Hint(id) => {
+            let game = player_game(&mut pebbles.games, &player, id)?;
+            hint(game, &mut pebbles.grundy_tables)
+        }
+        PebblesAction::Restart {
+            id,
+            difficulty,
+            pebbles_count,
+            max_pebbles_per_turn,
+            win_condition,
+            heaps,
+            allowed_moves,
+            rule_set,
+        } => {
+            let params = PebblesInit {
+                difficulty,
+                pebbles_count,
+                max_pebbles_per_turn,
+                win_condition,
+                heaps,
+                allowed_moves,
+                rule_set,
+                ..Default::default()
+            };
+            // 先做完所有可能失败的检查再认输：回复错误时状态不会回滚
+            validate_params(&params)?;
+            pebbles.covered_payout(value, &params.difficulty)?;
+            // 押了注或属于系列赛的对局不能靠重开躲过输局，只能先认输；
+            // 其余没下完的对局重开时按认输记下战绩和等级分
+            let series = pebbles.games.get(&id).and_then(|session| session.series);
+            let game = player_game(&mut pebbles.games, &player, id)?;
+            let rating_change = match game.winner {
+                Some(_) => None,
+                None if game.stake > 0 || series.is_some() => {
+                    return Err(PebblesError::GameInProgress);
+                }
+                None => {
+                    game.give_up(&Player::User)?;
+                    Some(end_game(pebbles, id, true))
+                }
+            };
+            let PebblesEvent::GameStarted {
+                id,
+                first_player,
+                remaining,
+            } = new_session(pebbles, player, id, params, value, None, None)?
+            else {
+                unreachable!("new_session() replies GameStarted");
+            };
+            Ok(PebblesEvent::Restarted {
+                id,
+                first_player,
+                remaining,
+                rating_change,
+            })
+        }
+        PebblesAction::Challenge(params) => challenge(pebbles, player, params, value),
+        PebblesAction::CancelChallenge => {
+            pebbles
+                .withdraw_challenge(&player)
+                .ok_or(PebblesError::NoChallenge)?;
+            Ok(PebblesEvent::ChallengeCancelled)
+        }
+        PebblesAction::AcceptChallenge(challenger) => {
+            accept_challenge(pebbles, player, challenger, value)
+        }
+        PebblesAction::DuelTurn { heap, count } => duel_turn(pebbles, player, heap, count),
+        PebblesAction::DuelGiveUp => duel_give_up(pebbles, player),
+        PebblesAction::CheckTimeout(id) => check_timeout(pebbles, id),
+        PebblesAction::CheckDuelTimeout(account) => check_duel_timeout(pebbles, account),
+        PebblesAction::JoinQueue {
+            rating_range,
+            pebbles_count,
+            max_pebbles_per_turn,
+        } => join_queue(
+            pebbles,
+            player,
+            QueueEntry {
+                rating_range,
+                pebbles_count,
+                max_pebbles_per_turn,
+                joined_at: exec::block_height(),
+            },
+        ),
+        PebblesAction::LeaveQueue => {
+            pebbles
+                .queue
+                .remove(&player)
+                .ok_or(PebblesError::NotQueued)?;
+            Ok(PebblesEvent::QueueLeft)
+        }
+        PebblesAction::CheckQueueExpiry(account) => check_queue_expiry(pebbles, account),
+        PebblesAction::CreateTournament {
+            format,
+            params,
+            entry_fee,
+            registration_deadline,
+        } => create_tournament(
+            pebbles,
+            player,
+            format,
+            params,
+            entry_fee,
+            registration_deadline,
+        ),
+        PebblesAction::RegisterForTournament(id) => register(pebbles, player, id, value),
+        PebblesAction::StartTournament(id) => start_tournament(pebbles, id),
+        PebblesAction::TournamentTurn { id, heap, count } => {
+            tournament_turn(pebbles, player, id, heap, count)
+        }
+        PebblesAction::TournamentGiveUp(id) => tournament_give_up(pebbles, player, id),
+        PebblesAction::CheckTournamentTimeout(id) => check_tournament_timeout(pebbles, id),
+    }
+}
+
+/// 用新的 GameId 开一局，回复 GameStarted
+fn open_session(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    params: PebblesInit,
+    stake: u128,
+    first_player: Option<Player>,
+    series: Option<SeriesId>,
+) -> Result<PebblesEvent, PebblesError> {
+    // 先占用 id：新的一局可能立刻结束，系列赛又会接着开下一局
+    let id = pebbles.next_game_id;
+    pebbles.next_game_id += 1;
+    let event = new_session(pebbles, player, id, params, stake, first_player, series)?;
+    pebbles.player_games.entry(player).or_default().push(id);
+    Ok(event)
+}
+
+/// 以 id 开一局新的（Restart 时替换原来那局），回复 GameStarted
+fn new_session(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    id: GameId,
+    params: PebblesInit,
+    stake: u128,
+    first_player: Option<Player>,
+    series: Option<SeriesId>,
+) -> Result<PebblesEvent, PebblesError> {
+    validate_params(&params)?;
+    let strategy = pebbles.strategy(&player, &params.difficulty);
+    pebbles.reserve_payout(stake, &params.difficulty)?;
+    let mut game = start_game(
+        params,
+        first_player,
+        &mut pebbles.grundy_tables,
+        strategy.as_ref(),
+        &mut pebbles.rng,
+    )
+    .expect("Validated parameters must start a game");
+    game.stake = stake;
+    game.started_at = exec::block_height();
+    let event = PebblesEvent::GameStarted {
+        id,
+        first_player: game.first_player.clone(),
+        remaining: game.pebbles_remaining,
+    };
+    let finished = game.winner.is_some();
+    pebbles.games.insert(
+        id,
+        Session {
+            player,
+            game,
+            series,
+        },
+    );
+    if finished {
+        end_game(pebbles, id, false);
+    } else {
+        schedule_timeout(pebbles, PebblesAction::CheckTimeout(id));
+    }
+    Ok(event)
+}
+
+/// 一局结束后结算（见 Pebbles::finish_game），系列赛中的一局还要推进系列赛；
+/// 返回玩家的等级分变化
+fn end_game(pebbles: &mut Pebbles, id: GameId, gave_up: bool) -> i32 {
+    let change = pebbles.finish_game(id, gave_up);
+    if let Some(series) = pebbles.games[&id].series {
+        advance_series(pebbles, series, id);
+    }
+    change
+}
+
+fn start_series(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    best_of: u32,
+    params: PebblesInit,
+) -> Result<PebblesEvent, PebblesError> {
+    if best_of % 2 != 1 {
+        return Err(PebblesError::InvalidSeriesLength);
+    }
+    pebbles.forget_finished_games(&player);
+    let id = pebbles.next_game_id;
+    pebbles.series.insert(
+        id,
+        Series {
+            player,
+            best_of,
+            params: params.clone(),
+            score: (0, 0),
+            winner: None,
+        },
+    );
+    let event = open_session(pebbles, player, params, 0, None, Some(id));
+    if event.is_err() {
+        pebbles.series.remove(&id);
+    }
+    event
+}
+
+/// 系列赛中的一局结束后记分；还没决出胜负就接着开下一局，由另一方先手
+fn advance_series(pebbles: &mut Pebbles, id: SeriesId, game_id: GameId) {
+    let Session { player, game, .. } = &pebbles.games[&game_id];
+    let player = *player;
+    let winner = game.winner.clone().expect("The series game is finished");
+    let first_player = game.opponent_of(&game.first_player);
+    let series = pebbles
+        .series
+        .get_mut(&id)
+        .expect("advance_series(): no such series");
+    match winner {
+        Player::User => series.score.0 += 1,
+        _ => series.score.1 += 1,
+    }
+    if series.score.0.max(series.score.1) > series.best_of / 2 {
+        series.winner = Some(winner.clone());
+        let event = PebblesEvent::SeriesWon {
+            series: id,
+            winner,
+            score: series.score,
+        };
+        notify(player, &event);
+        return;
+    }
+    let params = series.params.clone();
+    let event = open_session(pebbles, player, params, 0, Some(first_player), Some(id))
+        .expect("The series parameters are validated");
+    notify(player, &event);
+}
+
+fn turn(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    id: GameId,
+    heap: u32,
+    count: u32,
+) -> Result<PebblesEvent, PebblesError> {
+    let difficulty = player_game(&mut pebbles.games, &player, id)?
+        .difficulty
+        .clone();
+    let strategy = pebbles.strategy(&player, &difficulty);
+    let game = player_game(&mut pebbles.games, &player, id)?;
+    let mut event = user_turn(
+        game,
+        &mut pebbles.grundy_tables,
+        heap,
+        count,
+        strategy.as_ref(),
+        &mut pebbles.rng,
+    )?;
+    match &mut event {
+        PebblesEvent::Won { rating_change, .. } => *rating_change = end_game(pebbles, id, false),
+        _ => schedule_timeout(pebbles, PebblesAction::CheckTimeout(id)),
+    }
+    Ok(event)
+}
+
+/// 在这一步的期限到时检查是否超时
+fn schedule_timeout(pebbles: &Pebbles, check: PebblesAction) {
+    let timeout = pebbles.config.turn_timeout;
+    if timeout > 0 {
+        schedule_check(check, timeout);
+    }
+}
+
+/// 用预留的 gas 给自己发一条 delay 个区块后到达的检查消息
+fn schedule_check(check: PebblesAction, delay: u32) {
+    let reservation = ReservationId::reserve(TIMEOUT_GAS, delay + 1)
+        .expect("Unable to reserve gas for the delayed check");
+    msg::send_delayed_from_reservation(reservation, exec::program_id(), check, 0, delay)
+        .expect("Unable to schedule the delayed check");
+}
+
+fn is_overdue(pebbles: &Pebbles, game: &GameState) -> bool {
+    let timeout = pebbles.config.turn_timeout;
+    game.winner.is_none()
+        && timeout > 0
+        && exec::block_height() >= game.last_move_at().saturating_add(timeout)
+}
+
+/// 玩家超时未走，按认输处理；事件发给玩家本人，因为这条消息来自程序自己
+fn check_timeout(pebbles: &mut Pebbles, id: GameId) -> Result<PebblesEvent, PebblesError> {
+    let session = pebbles.games.get(&id).ok_or(PebblesError::NoActiveGame)?;
+    if !is_overdue(pebbles, &session.game) {
+        return Err(PebblesError::NotTimedOut);
+    }
+    let player = session.player;
+    let event = give_up(pebbles, player, id)?;
+    notify(player, &event);
+    Ok(event)
+}
+
+fn check_duel_timeout(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+) -> Result<PebblesEvent, PebblesError> {
+    let duel = pebbles.duel_mut(&player)?.clone();
+    if !is_overdue(pebbles, &duel.game) {
+        return Err(PebblesError::NotTimedOut);
+    }
+    let loser = duel_account(&duel, &duel.game.next_player());
+    let event = duel_give_up(pebbles, loser)?;
+    notify(loser, &event);
+    Ok(event)
+}
+
+/// player 自己的一局
+fn player_game<'a>(
+    games: &'a mut BTreeMap<GameId, Session>,
+    player: &ActorId,
+    id: GameId,
+) -> Result<&'a mut GameState, PebblesError> {
+    match games.get_mut(&id) {
+        Some(session) if session.player == *player => Ok(&mut session.game),
+        _ => Err(PebblesError::NoActiveGame),
+    }
+}
+
+/// 对局中 account 所在的一方
+fn duel_seat(duel: &Duel, account: &ActorId) -> Player {
+    if *account == duel.challenger {
+        Player::User
+    } else {
+        Player::Opponent
+    }
+}
+
+fn duel_account(duel: &Duel, player: &Player) -> ActorId {
+    match player {
+        Player::User => duel.challenger,
+        Player::Program | Player::Opponent => duel.opponent,
+    }
+}
+
+/// 转账给 account，附带 Payout 事件说明金额
+fn pay(account: ActorId, amount: u128) {
+    if amount == 0 {
+        return;
+    }
+    msg::send_with_gas(
+        account,
+        Ok::<_, PebblesError>(PebblesEvent::Payout(amount)),
+        0,
+        amount,
+    )
+    .expect("Unable to pay out");
+}
+
+/// 把事件也发给对手一份，格式和回复相同
+fn notify(account: ActorId, event: &PebblesEvent) {
+    msg::send(account, Ok::<_, PebblesError>(event.clone()), 0)
+        .expect("Unable to notify the opponent");
+}
+
+fn challenge(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    params: PebblesInit,
+    stake: u128,
+) -> Result<PebblesEvent, PebblesError> {
+    validate_params(&params)?;
+    if stake > 0 && pebbles.config.turn_timeout == 0 {
+        return Err(PebblesError::NoTurnTimeout);
+    }
+    if pebbles.duel_in_progress(&player) {
+        return Err(PebblesError::GameInProgress);
+    }
+    pebbles.withdraw_challenge(&player);
+    pebbles
+        .challenges
+        .insert(player, Challenge { params, stake });
+    pebbles.escrow += stake;
+    Ok(PebblesEvent::ChallengeCreated)
+}
+
+fn accept_challenge(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    challenger: ActorId,
+    stake: u128,
+) -> Result<PebblesEvent, PebblesError> {
+    if challenger == player {
+        return Err(PebblesError::CannotChallengeSelf);
+    }
+    if pebbles.duel_in_progress(&player) || pebbles.duel_in_progress(&challenger) {
+        return Err(PebblesError::GameInProgress);
+    }
+    let challenge = pebbles
+        .challenges
+        .remove(&challenger)
+        .ok_or(PebblesError::NoChallenge)?;
+    if stake != challenge.stake {
+        pebbles.challenges.insert(challenger, challenge);
+        return Err(PebblesError::WrongStake);
+    }
+    pebbles.escrow += stake;
+    let first_player = start_duel(pebbles, challenger, player, challenge.params, stake);
+    let event = PebblesEvent::DuelStarted {
+        challenger,
+        opponent: player,
+        first_player,
+    };
+    notify(challenger, &event);
+    Ok(event)
+}
+
+/// 用已验证的参数开始 challenger 与 opponent 的对局，返回先手的账户；
+/// 双方其他的挑战撤回并退还赌注，也都离开大厅
+fn start_duel(
+    pebbles: &mut Pebbles,
+    challenger: ActorId,
+    opponent: ActorId,
+    params: PebblesInit,
+    stake: u128,
+) -> ActorId {
+    let first_player = match pebbles.rng.next_u32() % 2 {
+        0 => Player::User,
+        _ => Player::Opponent,
+    };
+    let mut game =
+        GameState::new_pvp(params, first_player.clone()).expect("Duel parameters are validated");
+    game.stake = stake;
+    game.started_at = exec::block_height();
+
+    pebbles.forget_duel(&challenger);
+    pebbles.forget_duel(&opponent);
+    for account in [challenger, opponent] {
+        pebbles.withdraw_challenge(&account);
+        pebbles.queue.remove(&account);
+    }
+    let duel = Duel {
+        challenger,
+        opponent,
+        game,
+    };
+    let first_player = duel_account(&duel, &first_player);
+    pebbles.duels.insert(challenger, duel);
+    pebbles.duel_of.insert(challenger, challenger);
+    pebbles.duel_of.insert(opponent, challenger);
+    schedule_timeout(pebbles, PebblesAction::CheckDuelTimeout(challenger));
+    first_player
+}
+
+/// 和等得最久的合适玩家开局；没有的话进入大厅等待
+fn join_queue(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    entry: QueueEntry,
+) -> Result<PebblesEvent, PebblesError> {
+    let params = PebblesInit {
+        pebbles_count: entry.pebbles_count,
+        max_pebbles_per_turn: entry.max_pebbles_per_turn,
+        ..Default::default()
+    };
+    validate_params(&params)?;
+    if pebbles.duel_in_progress(&player) {
+        return Err(PebblesError::GameInProgress);
+    }
+    pebbles.queue.remove(&player);
+
+    let rating = pebbles.rating(&player);
+    let waiting = pebbles
+        .queue
+        .iter()
+        .filter(|(account, waiting)| {
+            let range = waiting.rating_range.min(entry.rating_range);
+            waiting.pebbles_count == entry.pebbles_count
+                && waiting.max_pebbles_per_turn == entry.max_pebbles_per_turn
+                && pebbles.rating(account).abs_diff(rating) <= range
+        })
+        .min_by_key(|(_, waiting)| waiting.joined_at)
+        .map(|(account, _)| *account);
+    let Some(waiting) = waiting else {
+        pebbles.queue.insert(player, entry);
+        schedule_check(PebblesAction::CheckQueueExpiry(player), QUEUE_TTL);
+        return Ok(PebblesEvent::Queued);
+    };
+
+    let first_player = start_duel(pebbles, waiting, player, params, 0);
+    let event = PebblesEvent::Matched {
+        waiting,
+        joined: player,
+        first_player,
+    };
+    notify(waiting, &event);
+    Ok(event)
+}
+
+/// 等待超过 QUEUE_TTL 的玩家离开大厅，并收到 QueueLeft
+fn check_queue_expiry(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+) -> Result<PebblesEvent, PebblesError> {
+    let entry = pebbles.queue.get(&player).ok_or(PebblesError::NotQueued)?;
+    if exec::block_height() < entry.joined_at.saturating_add(QUEUE_TTL) {
+        return Err(PebblesError::NotTimedOut);
+    }
+    pebbles.queue.remove(&player);
+    let event = PebblesEvent::QueueLeft;
+    notify(player, &event);
+    Ok(event)
+}
+
+fn duel_turn(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    heap: u32,
+    count: u32,
+) -> Result<PebblesEvent, PebblesError> {
+    let duel = pebbles.duel_mut(&player)?;
+    let seat = duel_seat(duel, &player);
+    duel.game
+        .apply_move(seat.clone(), heap, count, exec::block_height())?;
+
+    let rival = duel_account(duel, &duel.game.opponent_of(&seat));
+    let winner = duel
+        .game
+        .winner
+        .as_ref()
+        .map(|winner| duel_account(duel, winner));
+    let stake = duel.game.stake;
+    let event = match winner {
+        Some(winner) => {
+            let loser = if winner == player { rival } else { player };
+            PebblesEvent::DuelWon {
+                winner,
+                rating_change: finish_duel(pebbles, winner, loser, stake),
+            }
+        }
+        None => {
+            schedule_timeout(pebbles, PebblesAction::CheckDuelTimeout(player));
+            PebblesEvent::DuelMove {
+                player,
+                heap,
+                pebbles: count,
+            }
+        }
+    };
+    notify(rival, &event);
+    Ok(event)
+}
+
+/// 对局结束：更新双方的等级分，赢家拿走双方的赌注；返回赢家的等级分变化
+fn finish_duel(pebbles: &mut Pebbles, winner: ActorId, loser: ActorId, stake: u128) -> i32 {
+    let change = rating_change(pebbles.rating(&winner), pebbles.rating(&loser), true);
+    pebbles.update_rating(winner, change);
+    pebbles.update_rating(loser, -change);
+    pebbles.escrow -= 2 * stake;
+    pay(winner, 2 * stake);
+    change
+}
+
+fn duel_give_up(pebbles: &mut Pebbles, player: ActorId) -> Result<PebblesEvent, PebblesError> {
+    let duel = pebbles.duel_mut(&player)?;
+    let seat = duel_seat(duel, &player);
+    duel.game.give_up(&seat)?;
+
+    let rival = duel_account(duel, &duel.game.opponent_of(&seat));
+    let stake = duel.game.stake;
+    let event = PebblesEvent::DuelWon {
+        winner: rival,
+        rating_change: finish_duel(pebbles, rival, player, stake),
+    };
+    notify(rival, &event);
+    Ok(event)
+}
+
+fn create_tournament(
+    pebbles: &mut Pebbles,
+    organiser: ActorId,
+    format: TournamentFormat,
+    params: PebblesInit,
+    entry_fee: u128,
+    registration_deadline: u32,
+) -> Result<PebblesEvent, PebblesError> {
+    validate_params(&params)?;
+    if entry_fee > 0 && pebbles.config.turn_timeout == 0 {
+        return Err(PebblesError::NoTurnTimeout);
+    }
+    let now = exec::block_height();
+    if registration_deadline <= now || registration_deadline - now > MAX_REGISTRATION_BLOCKS {
+        return Err(PebblesError::InvalidDeadline);
+    }
+    let id = pebbles.next_tournament_id;
+    pebbles.next_tournament_id += 1;
+    pebbles.tournaments.insert(
+        id,
+        Tournament {
+            organiser,
+            format,
+            params,
+            entry_fee,
+            registration_deadline,
+            stage: TournamentStage::Registration,
+            players: Vec::new(),
+            wins: Vec::new(),
+            alive: Vec::new(),
+            round: 0,
+            matches: Vec::new(),
+            winners: Vec::new(),
+        },
+    );
+    schedule_check(
+        PebblesAction::StartTournament(id),
+        registration_deadline - now,
+    );
+    Ok(PebblesEvent::TournamentCreated(id))
+}
+
+fn register(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    id: TournamentId,
+    fee: u128,
+) -> Result<PebblesEvent, PebblesError> {
+    let tournament = pebbles
+        .tournaments
+        .get_mut(&id)
+        .ok_or(PebblesError::NoTournament)?;
+    if tournament.stage != TournamentStage::Registration
+        || exec::block_height() >= tournament.registration_deadline
+    {
+        return Err(PebblesError::RegistrationClosed);
+    }
+    if tournament.players.contains(&player) {
+        return Err(PebblesError::AlreadyRegistered);
+    }
+    if tournament.players.len() >= MAX_TOURNAMENT_PLAYERS {
+        return Err(PebblesError::TournamentFull);
+    }
+    if fee != tournament.entry_fee {
+        return Err(PebblesError::WrongStake);
+    }
+    tournament.players.push(player);
+    pebbles.escrow += fee;
+    Ok(PebblesEvent::Registered(id))
+}
+
+/// 报名截止后随机排好对阵并开始第一轮；不足两人时取消并退还报名费
+fn start_tournament(pebbles: &mut Pebbles, id: TournamentId) -> Result<PebblesEvent, PebblesError> {
+    let tournament = pebbles
+        .tournaments
+        .get_mut(&id)
+        .ok_or(PebblesError::NoTournament)?;
+    if tournament.stage != TournamentStage::Registration {
+        return Err(PebblesError::RegistrationClosed);
+    }
+    if exec::block_height() < tournament.registration_deadline {
+        return Err(PebblesError::NotTimedOut);
+    }
+
+    if tournament.players.len() < 2 {
+        tournament.stage = TournamentStage::Cancelled;
+        let event = PebblesEvent::TournamentCancelled(id);
+        for player in &tournament.players {
+            pebbles.escrow -= tournament.entry_fee;
+            pay(*player, tournament.entry_fee);
+            notify(*player, &event);
+        }
+        return Ok(event);
+    }
+
+    let rng = &mut pebbles.rng;
+    let players = &mut tournament.players;
+    for i in (1..players.len()).rev() {
+        players.swap(i, rng.next_u32() as usize % (i + 1));
+    }
+    tournament.wins = vec![0; players.len()];
+    tournament.alive = players.clone();
+    tournament.stage = TournamentStage::Running;
+    start_round(pebbles, id);
+    Ok(PebblesEvent::TournamentStarted(id))
+}
+
+/// 为当前轮次的每一组对阵开一局，通知双方
+fn start_round(pebbles: &mut Pebbles, id: TournamentId) {
+    let tournament = pebbles
+        .tournaments
+        .get_mut(&id)
+        .expect("start_round(): no such tournament");
+    let pairs: Vec<_> = match tournament.format {
+        TournamentFormat::SingleElimination => tournament
+            .alive
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect(),
+        TournamentFormat::RoundRobin => {
+            round_robin_pairings(tournament.players.len() as u32, tournament.round)
+                .into_iter()
+                .map(|(first, second)| {
+                    (
+                        tournament.players[first as usize],
+                        tournament.players[second as usize],
+                    )
+                })
+                .collect()
+        }
+    };
+
+    let rng = &mut pebbles.rng;
+    tournament.matches = pairs
+        .into_iter()
+        .map(|players| {
+            let first_player = match rng.next_u32() % 2 {
+                0 => Player::User,
+                _ => Player::Opponent,
+            };
+            let mut game = GameState::new_pvp(tournament.params.clone(), first_player)
+                .expect("Tournament parameters are validated");
+            game.started_at = exec::block_height();
+            TournamentMatch { players, game }
+        })
+        .collect();
+    for tournament_match in &tournament.matches {
+        let (first, second) = tournament_match.players;
+        let event = PebblesEvent::MatchStarted {
+            tournament: id,
+            round: tournament.round,
+            players: tournament_match.players,
+            first_player: match_account(tournament_match, &tournament_match.game.first_player),
+        };
+        notify(first, &event);
+        notify(second, &event);
+    }
+    schedule_timeout(pebbles, PebblesAction::CheckTournamentTimeout(id));
+}
+
+/// player 在当前轮次中还没结束的比赛
+fn tournament_match<'a>(
+    tournaments: &'a mut BTreeMap<TournamentId, Tournament>,
+    player: &ActorId,
+    id: TournamentId,
+) -> Result<&'a mut TournamentMatch, PebblesError> {
+    tournaments
+        .get_mut(&id)
+        .ok_or(PebblesError::NoTournament)?
+        .matches
+        .iter_mut()
+        .find(|tournament_match| {
+            let (first, second) = tournament_match.players;
+            tournament_match.game.winner.is_none() && (first == *player || second == *player)
+        })
+        .ok_or(PebblesError::NoActiveGame)
+}
+
+/// 比赛中 account 所在的一方
+fn match_seat(tournament_match: &TournamentMatch, account: &ActorId) -> Player {
+    if *account == tournament_match.players.0 {
+        Player::User
+    } else {
+        Player::Opponent
+    }
+}
+
+fn match_account(tournament_match: &TournamentMatch, player: &Player) -> ActorId {
+    match player {
+        Player::User => tournament_match.players.0,
+        Player::Program | Player::Opponent => tournament_match.players.1,
+    }
+}
+
+fn tournament_turn(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    id: TournamentId,
+    heap: u32,
+    count: u32,
+) -> Result<PebblesEvent, PebblesError> {
+    let tournament_match = tournament_match(&mut pebbles.tournaments, &player, id)?;
+    let seat = match_seat(tournament_match, &player);
+    tournament_match
+        .game
+        .apply_move(seat.clone(), heap, count, exec::block_height())?;
+
+    let game = &tournament_match.game;
+    let rival = match_account(tournament_match, &game.opponent_of(&seat));
+    let winner = game
+        .winner
+        .as_ref()
+        .map(|winner| match_account(tournament_match, winner));
+    let event = match winner {
+        Some(winner) => {
+            let loser = if winner == player { rival } else { player };
+            finish_match(pebbles, id, winner, loser)
+        }
+        None => {
+            schedule_timeout(pebbles, PebblesAction::CheckTournamentTimeout(id));
+            PebblesEvent::MatchMove {
+                tournament: id,
+                player,
+                heap,
+                pebbles: count,
+            }
+        }
+    };
+    notify(rival, &event);
+    advance_tournament(pebbles, id);
+    Ok(event)
+}
+
+fn tournament_give_up(
+    pebbles: &mut Pebbles,
+    player: ActorId,
+    id: TournamentId,
+) -> Result<PebblesEvent, PebblesError> {
+    let tournament_match = tournament_match(&mut pebbles.tournaments, &player, id)?;
+    let seat = match_seat(tournament_match, &player);
+    tournament_match.game.give_up(&seat)?;
+
+    let rival = match_account(tournament_match, &tournament_match.game.opponent_of(&seat));
+    let event = finish_match(pebbles, id, rival, player);
+    notify(rival, &event);
+    advance_tournament(pebbles, id);
+    Ok(event)
+}
+
+/// 当前轮次中超时未走的玩家都按认输处理；回复最后一场的结果
+fn check_tournament_timeout(
+    pebbles: &mut Pebbles,
+    id: TournamentId,
+) -> Result<PebblesEvent, PebblesError> {
+    let tournament = pebbles
+        .tournaments
+        .get(&id)
+        .ok_or(PebblesError::NoTournament)?;
+    let losers: Vec<_> = tournament
+        .matches
+        .iter()
+        .filter(|tournament_match| is_overdue(pebbles, &tournament_match.game))
+        .map(|tournament_match| {
+            match_account(tournament_match, &tournament_match.game.next_player())
+        })
+        .collect();
+    let mut event = Err(PebblesError::NotTimedOut);
+    for loser in losers {
+        let lost = tournament_give_up(pebbles, loser, id)?;
+        notify(loser, &lost);
+        event = Ok(lost);
+    }
+    event
+}
+
+/// 一场比赛结束：和 PvP 对局一样更新双方的等级分，记下赢家的胜场
+fn finish_match(
+    pebbles: &mut Pebbles,
+    id: TournamentId,
+    winner: ActorId,
+    loser: ActorId,
+) -> PebblesEvent {
+    let change = rating_change(pebbles.rating(&winner), pebbles.rating(&loser), true);
+    pebbles.update_rating(winner, change);
+    pebbles.update_rating(loser, -change);
+    let tournament = pebbles
+        .tournaments
+        .get_mut(&id)
+        .expect("finish_match(): no such tournament");
+    if let Some(index) = tournament
+        .players
+        .iter()
+        .position(|player| *player == winner)
+    {
+        tournament.wins[index] += 1;
+    }
+    PebblesEvent::MatchWon {
+        tournament: id,
+        winner,
+        rating_change: change,
+    }
+}
+
+/// 本轮比赛全部结束后开始下一轮，或者结束比赛并分配奖池
+fn advance_tournament(pebbles: &mut Pebbles, id: TournamentId) {
+    let tournament = pebbles
+        .tournaments
+        .get_mut(&id)
+        .expect("advance_tournament(): no such tournament");
+    if tournament
+        .matches
+        .iter()
+        .any(|tournament_match| tournament_match.game.winner.is_none())
+    {
+        return;
+    }
+
+    let finished = match tournament.format {
+        TournamentFormat::SingleElimination => {
+            let losers: Vec<_> = tournament
+                .matches
+                .iter()
+                .map(|tournament_match| {
+                    let game = &tournament_match.game;
+                    let winner = game.winner.as_ref().expect("The match is finished");
+                    match_account(tournament_match, &game.opponent_of(winner))
+                })
+                .collect();
+            let sat_out = tournament.alive.len() % 2 == 1;
+            tournament.alive.retain(|player| !losers.contains(player));
+            if sat_out {
+                // 轮空的玩家排在最后；把他换到最前面，下一轮不会再轮空
+                tournament.alive.rotate_right(1);
+            }
+            tournament.alive.len() < 2
+        }
+        TournamentFormat::RoundRobin => {
+            tournament.round + 1 >= round_robin_rounds(tournament.players.len() as u32)
+        }
+    };
+    if !finished {
+        tournament.round += 1;
+        start_round(pebbles, id);
+        return;
+    }
+
+    tournament.winners = match tournament.format {
+        TournamentFormat::SingleElimination => tournament.alive.clone(),
+        TournamentFormat::RoundRobin => {
+            let most = tournament.wins.iter().copied().max().unwrap_or_default();
+            tournament
+                .players
+                .iter()
+                .zip(&tournament.wins)
+                .filter(|(_, wins)| **wins == most)
+                .map(|(player, _)| *player)
+                .collect()
+        }
+    };
+    tournament.stage = TournamentStage::Finished;
+    let pool = tournament.entry_fee * tournament.players.len() as u128;
+    let prize = pool / tournament.winners.len() as u128;
+    pebbles.escrow -= pool;
+    for (place, winner) in tournament.winners.iter().enumerate() {
+        let remainder = if place == 0 {
+            pool % tournament.winners.len() as u128
+        } else {
+            0
+        };
+        pay(*winner, prize + remainder);
+    }
+    let event = PebblesEvent::TournamentWon {
+        tournament: id,
+        winners: tournament.winners.clone(),
+        prize,
+    };
+    for player in &tournament.players {
+        notify(*player, &event);
+    }
 }
 
 #[no_mangle]
-pub extern "C" fn handle() {
-    // 我的代码
+extern "C" fn handle() {
+    let action: PebblesAction = msg::load().expect("Unable to decode PebblesAction");
+    let pebbles =
+        unsafe { (*addr_of_mut!(PEBBLES)).as_mut() }.expect("The program is not initialized");
+
+    let value = msg::value();
+    let refund = if takes_stake(&action) { 0 } else { value };
+    let reply = process_action(pebbles, msg::source(), action, value);
+    let refund = if reply.is_err() { value } else { refund };
+    msg::reply(reply, refund).expect("Unable to reply");
+}
+
+fn query_state(pebbles: &Pebbles, query: StateQuery) -> StateReply {
+    match query {
+        StateQuery::Game(id) => {
+            StateReply::Game(pebbles.games.get(&id).map(|session| session.game.clone()))
+        }
+        StateQuery::Winner(id) => StateReply::Winner(
+            pebbles
+                .games
+                .get(&id)
+                .and_then(|session| session.game.winner.clone()),
+        ),
+        StateQuery::LegalMoves(id) => StateReply::LegalMoves(
+            pebbles
+                .games
+                .get(&id)
+                .map(|session| session.game.legal_moves())
+                .unwrap_or_default(),
+        ),
+        StateQuery::History(id, page) => StateReply::History(
+            pebbles
+                .games
+                .get(&id)
+                .map(|session| {
+                    session
+                        .game
+                        .moves
+                        .iter()
+                        .skip(page as usize * HISTORY_PAGE_SIZE)
+                        .take(HISTORY_PAGE_SIZE)
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default(),
+        ),
+        StateQuery::Series(id) => StateReply::Series(pebbles.series.get(&id).cloned()),
+        StateQuery::Config => StateReply::Config(pebbles.config.clone()),
+        StateQuery::ActiveGames(player) => StateReply::ActiveGames(
+            pebbles
+                .player_games
+                .get(&player)
+                .map(|ids| {
+                    ids.iter()
+                        .copied()
+                        .filter(|id| pebbles.games[id].game.winner.is_none())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        ),
+        StateQuery::Leaderboard(count) => {
+            let mut leaderboard: Vec<_> = pebbles
+                .stats
+                .iter()
+                .map(|(player, stats)| (*player, stats.overall.clone()))
+                .collect();
+            leaderboard.sort_by_key(|(_, stats)| {
+                (
+                    Reverse(stats.wins),
+                    Reverse(stats.best_streak),
+                    stats.losses + stats.give_ups,
+                )
+            });
+            leaderboard.truncate(count as usize);
+            StateReply::Leaderboard(leaderboard)
+        }
+        StateQuery::PlayerStats(player) => {
+            StateReply::PlayerStats(pebbles.stats.get(&player).cloned())
+        }
+        StateQuery::Rating(player) => StateReply::Rating(pebbles.rating(&player)),
+        StateQuery::Challenges => StateReply::Challenges(
+            pebbles
+                .challenges
+                .iter()
+                .map(|(challenger, challenge)| (*challenger, challenge.clone()))
+                .collect(),
+        ),
+        StateQuery::Duel(player) => StateReply::Duel(
+            pebbles
+                .duel_of
+                .get(&player)
+                .and_then(|challenger| pebbles.duels.get(challenger))
+                .cloned(),
+        ),
+        StateQuery::Tournament(id) => StateReply::Tournament(pebbles.tournaments.get(&id).cloned()),
+        StateQuery::Queue => StateReply::Queue(
+            pebbles
+                .queue
+                .iter()
+                .map(|(player, entry)| (*player, entry.clone()))
+                .collect(),
+        ),
+    }
 }
 
 #[no_mangle]
-pub extern "C" fn state() {
-    // 我的代码
-}
\ No newline at end of file
+extern "C" fn state() {
+    let query: StateQuery = msg::load().expect("Unable to decode StateQuery");
+    let pebbles = unsafe { (*addr_of!(PEBBLES)).as_ref() }.expect("The program is not initialized");
+    msg::reply(query_state(pebbles, query), 0).expect("Unable to share the state");
+}
@@ -0,0 +1,1182 @@
+use gstd::ActorId;
+use gtest::{Log, Program, RunResult, System};
+use pebbles_game_core::{Rng, SeededRng};
+use pebbles_game_io::*;
+
+const USER: u64 = 42;
+const RIVAL: u64 = 43;
+const HOUSE: u64 = 44;
+/// Every account the tests send from
+const ACCOUNTS: [u64; 5] = [USER, RIVAL, HOUSE, 45, 46];
+const BANKROLL: u128 = 100_000_000_000_000;
+const STAKE: u128 = 10_000_000_000_000;
+
+/// The first player the program picks when initialized with `seed`
+fn first_player(seed: u64) -> Player {
+    match SeededRng::new(seed).next_u32() % 2 {
+        0 => Player::User,
+        _ => Player::Program,
+    }
+}
+
+/// A seed for which the first game of a freshly initialized program starts with `player`
+fn seed_for(player: Player) -> u64 {
+    (0..)
+        .find(|seed| first_player(*seed) == player)
+        .expect("No seed found")
+}
+
+fn params(
+    difficulty: DifficultyLevel,
+    pebbles_count: u32,
+    max_pebbles_per_turn: u32,
+) -> PebblesInit {
+    PebblesInit {
+        difficulty,
+        pebbles_count,
+        max_pebbles_per_turn,
+        ..Default::default()
+    }
+}
+
+fn init(sys: &System, mut init: PebblesInit, first: Player) -> Program<'_> {
+    sys.init_logger();
+    init.seed = Some(seed_for(first));
+    let program = Program::current(sys);
+    let res = program.send(USER, init);
+    assert!(!res.main_failed());
+    program
+}
+
+/// Like [`init`], with the house funding the program's bankroll
+fn init_with_bankroll(sys: &System, mut init: PebblesInit, first: Player) -> Program<'_> {
+    sys.init_logger();
+    for account in [USER, RIVAL, HOUSE] {
+        sys.mint_to(account, 2 * BANKROLL);
+    }
+    init.seed = Some(seed_for(first));
+    let program = Program::current(sys);
+    let res = program.send_with_value(HOUSE, init, BANKROLL);
+    assert!(!res.main_failed());
+    program
+}
+
+/// Claims a payout from the mailbox of `account` and checks the balance it adds
+fn claim_payout(sys: &System, account: u64, amount: u128) {
+    let balance = sys.balance_of(account);
+    sys.get_mailbox(account)
+        .claim_value(
+            Log::builder()
+                .dest(account)
+                .payload(Ok::<_, PebblesError>(PebblesEvent::Payout(amount))),
+        )
+        .expect("No payout in the mailbox");
+    assert_eq!(sys.balance_of(account), balance + amount);
+}
+
+fn replied(res: &RunResult, reply: Result<PebblesEvent, PebblesError>) -> bool {
+    sent_to(res, USER, reply)
+}
+
+fn sent_to(res: &RunResult, account: u64, reply: Result<PebblesEvent, PebblesError>) -> bool {
+    res.contains(&Log::builder().dest(account).payload(reply))
+}
+
+fn game(program: &Program, id: GameId) -> GameState {
+    let reply: StateReply = program
+        .read_state(StateQuery::Game(id))
+        .expect("Unable to read the state");
+    match reply {
+        StateReply::Game(Some(game)) => game,
+        reply => panic!("Unexpected state reply: {reply:?}"),
+    }
+}
+
+fn active_games(program: &Program, account: u64) -> Vec<GameId> {
+    let reply: StateReply = program
+        .read_state(StateQuery::ActiveGames(account.into()))
+        .expect("Unable to read the state");
+    match reply {
+        StateReply::ActiveGames(ids) => ids,
+        reply => panic!("Unexpected state reply: {reply:?}"),
+    }
+}
+
+/// The `Won` reply ending a player's first rated game at `difficulty`
+fn first_won(winner: Player, difficulty: &DifficultyLevel) -> PebblesEvent {
+    let user_won = winner == Player::User;
+    PebblesEvent::Won {
+        winner,
+        rating_change: rating_change(INITIAL_RATING, bot_rating(difficulty), user_won),
+    }
+}
+
+/// The `DuelWon` reply ending a duel between two unrated players
+fn first_duel_won(winner: u64) -> PebblesEvent {
+    PebblesEvent::DuelWon {
+        winner: winner.into(),
+        rating_change: rating_change(INITIAL_RATING, INITIAL_RATING, true),
+    }
+}
+
+fn tournament(program: &Program, id: TournamentId) -> Tournament {
+    let reply: StateReply = program
+        .read_state(StateQuery::Tournament(id))
+        .expect("Unable to read the state");
+    match reply {
+        StateReply::Tournament(Some(tournament)) => tournament,
+        reply => panic!("Unexpected state reply: {reply:?}"),
+    }
+}
+
+fn rating(program: &Program, account: u64) -> u32 {
+    let reply: StateReply = program
+        .read_state(StateQuery::Rating(account.into()))
+        .expect("Unable to read the state");
+    match reply {
+        StateReply::Rating(rating) => rating,
+        reply => panic!("Unexpected state reply: {reply:?}"),
+    }
+}
+
+fn account(actor: ActorId) -> u64 {
+    ACCOUNTS
+        .into_iter()
+        .find(|account| ActorId::from(*account) == actor)
+        .expect("Unknown account")
+}
+
+/// Plays the current round of a tournament whose matches are on a heap of 3
+/// with up to 3 pebbles per turn, so whoever moves takes the heap and wins
+fn play_round(program: &Program, id: TournamentId) {
+    for tournament_match in &tournament(program, id).matches {
+        let game = &tournament_match.game;
+        if game.winner.is_some() {
+            continue;
+        }
+        let (first, second) = tournament_match.players;
+        let (winner, loser) = match game.next_player() {
+            Player::User => (first, second),
+            _ => (second, first),
+        };
+        let won = PebblesEvent::MatchWon {
+            tournament: id,
+            winner,
+            rating_change: rating_change(
+                rating(program, account(winner)),
+                rating(program, account(loser)),
+                true,
+            ),
+        };
+        let turn = PebblesAction::TournamentTurn {
+            id,
+            heap: 0,
+            count: 3,
+        };
+        let res = program.send(account(winner), turn);
+        assert!(sent_to(&res, account(winner), Ok(won.clone())));
+        assert!(sent_to(&res, account(loser), Ok(won)));
+    }
+}
+
+/// Plays rounds with [`play_round`] until the tournament is over
+fn play_tournament(program: &Program, id: TournamentId) -> Tournament {
+    loop {
+        let tournament = tournament(program, id);
+        if tournament.stage != TournamentStage::Running {
+            return tournament;
+        }
+        play_round(program, id);
+    }
+}
+
+fn last_program_move(game: &GameState) -> u32 {
+    let last = game.moves.last().expect("No moves recorded");
+    assert_eq!(last.player, Player::Program);
+    last.pebbles
+}
+
+#[test]
+fn init_rejects_invalid_params() {
+    let sys = System::new();
+    sys.init_logger();
+
+    for init in [
+        params(DifficultyLevel::Easy, 0, 1),
+        params(DifficultyLevel::Easy, 10, 0),
+        params(DifficultyLevel::Hard, 10, 11),
+    ] {
+        let program = Program::current(&sys);
+        let res = program.send(USER, init);
+        assert!(res.main_failed());
+    }
+}
+
+#[test]
+fn user_moves_first() {
+    for difficulty in [DifficultyLevel::Easy, DifficultyLevel::Hard] {
+        let sys = System::new();
+        let program = init(&sys, params(difficulty, 10, 3), Player::User);
+
+        let res = program.send(USER, PebblesAction::StartGame(None));
+        let started = PebblesEvent::GameStarted {
+            id: 0,
+            first_player: Player::User,
+            remaining: 10,
+        };
+        assert!(replied(&res, Ok(started)));
+
+        let game = game(&program, 0);
+        assert_eq!(game.first_player, Player::User);
+        assert_eq!(game.pebbles_remaining, 10);
+        assert!(game.moves.is_empty());
+        assert!(game.winner.is_none());
+    }
+}
+
+#[test]
+fn program_moves_first() {
+    for difficulty in [DifficultyLevel::Easy, DifficultyLevel::Hard] {
+        let sys = System::new();
+        let program = init(&sys, params(difficulty.clone(), 10, 3), Player::Program);
+
+        let res = program.send(USER, PebblesAction::StartGame(None));
+        let game = game(&program, 0);
+        assert_eq!(game.first_player, Player::Program);
+        assert_eq!(game.moves.len(), 1);
+        let taken = last_program_move(&game);
+        assert_eq!(game.pebbles_remaining, 10 - taken);
+        let started = PebblesEvent::GameStarted {
+            id: 0,
+            first_player: Player::Program,
+            remaining: game.pebbles_remaining,
+        };
+        assert!(replied(&res, Ok(started)));
+        if let DifficultyLevel::Hard = difficulty {
+            // 10 % 4 == 2: the optimal opening leaves a multiple of 4
+            assert_eq!(taken, 2);
+        }
+    }
+}
+
+#[test]
+fn user_wins_with_optimal_play() {
+    for difficulty in [DifficultyLevel::Easy, DifficultyLevel::Hard] {
+        let sys = System::new();
+        let program = init(&sys, params(difficulty.clone(), 10, 3), Player::User);
+        program.send(USER, PebblesAction::StartGame(None));
+
+        loop {
+            let remaining = game(&program, 0).pebbles_remaining;
+            let take = remaining % 4;
+            let res = program.send(USER, PebblesAction::Turn { id: 0, count: take });
+            if take == remaining {
+                assert!(replied(&res, Ok(first_won(Player::User, &difficulty))));
+                break;
+            }
+            let taken = last_program_move(&game(&program, 0));
+            assert!(replied(&res, Ok(PebblesEvent::CounterTurn(taken))));
+        }
+
+        let game = game(&program, 0);
+        assert_eq!(game.winner, Some(Player::User));
+        assert_eq!(game.pebbles_remaining, 0);
+    }
+}
+
+#[test]
+fn hard_program_wins_a_losing_position_for_the_user() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Hard, 12, 3), Player::User);
+    program.send(USER, PebblesAction::StartGame(None));
+
+    for _ in 0..2 {
+        let res = program.send(USER, PebblesAction::Turn { id: 0, count: 1 });
+        assert!(replied(&res, Ok(PebblesEvent::CounterTurn(3))));
+    }
+    let res = program.send(USER, PebblesAction::Turn { id: 0, count: 1 });
+    let won = first_won(Player::Program, &DifficultyLevel::Hard);
+    assert!(replied(&res, Ok(won)));
+
+    let res = program.send(USER, PebblesAction::Turn { id: 0, count: 1 });
+    assert!(replied(&res, Err(PebblesError::GameOver)));
+    assert_eq!(game(&program, 0).winner, Some(Player::Program));
+}
+
+#[test]
+fn invalid_turns_are_rejected() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+
+    let res = program.send(USER, PebblesAction::Turn { id: 0, count: 1 });
+    assert!(replied(&res, Err(PebblesError::NoActiveGame)));
+
+    program.send(USER, PebblesAction::StartGame(None));
+    let res = program.send(USER, PebblesAction::Turn { id: 0, count: 0 });
+    assert!(replied(&res, Err(PebblesError::ZeroPebbles)));
+    let res = program.send(USER, PebblesAction::Turn { id: 0, count: 4 });
+    assert!(replied(&res, Err(PebblesError::TooManyPebbles)));
+    let res = program.send(
+        USER,
+        PebblesAction::TurnOnHeap {
+            id: 0,
+            heap: 1,
+            count: 1,
+        },
+    );
+    assert!(replied(&res, Err(PebblesError::InvalidHeap)));
+    // Only the account that started a game may play it
+    let res = program.send(RIVAL, PebblesAction::Turn { id: 0, count: 1 });
+    assert!(sent_to(&res, RIVAL, Err(PebblesError::NoActiveGame)));
+
+    let game = game(&program, 0);
+    assert_eq!(game.pebbles_remaining, 10);
+    assert!(game.moves.is_empty());
+}
+
+#[test]
+fn give_up() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+    program.send(USER, PebblesAction::StartGame(None));
+
+    let res = program.send(USER, PebblesAction::GiveUp(0));
+    let won = first_won(Player::Program, &DifficultyLevel::Easy);
+    assert!(replied(&res, Ok(won)));
+    assert_eq!(game(&program, 0).winner, Some(Player::Program));
+
+    let res = program.send(USER, PebblesAction::GiveUp(0));
+    assert!(replied(&res, Err(PebblesError::GameOver)));
+}
+
+#[test]
+fn restart_with_new_params() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+    program.send(USER, PebblesAction::StartGame(None));
+    program.send(USER, PebblesAction::Turn { id: 0, count: 2 });
+
+    let res = program.send(
+        USER,
+        PebblesAction::Restart {
+            id: 0,
+            difficulty: DifficultyLevel::Hard,
+            pebbles_count: 25,
+            max_pebbles_per_turn: 4,
+            win_condition: WinCondition::Normal,
+            heaps: vec![],
+            allowed_moves: vec![],
+            rule_set: RuleSet::Standard,
+        },
+    );
+    let game = game(&program, 0);
+    assert_eq!(game.pebbles_count, 25);
+    assert_eq!(game.max_pebbles_per_turn, 4);
+    assert!(matches!(game.difficulty, DifficultyLevel::Hard));
+    assert!(game.winner.is_none());
+    // The unfinished game counts as given up
+    let lost = rating_change(INITIAL_RATING, bot_rating(&DifficultyLevel::Easy), false);
+    let restarted = PebblesEvent::Restarted {
+        id: 0,
+        first_player: game.first_player.clone(),
+        remaining: game.pebbles_remaining,
+        rating_change: Some(lost),
+    };
+    assert!(replied(&res, Ok(restarted)));
+    assert_eq!(
+        rating(&program, USER),
+        INITIAL_RATING.saturating_add_signed(lost)
+    );
+    match game.first_player {
+        Player::User => assert_eq!(game.pebbles_remaining, 25),
+        // 25 % 5 == 0: the optimal opening is not winning, so Hard delays with 1
+        _ => assert_eq!(game.pebbles_remaining, 24),
+    }
+
+    let res = program.send(
+        USER,
+        PebblesAction::Restart {
+            id: 0,
+            difficulty: DifficultyLevel::Hard,
+            pebbles_count: 5,
+            max_pebbles_per_turn: 6,
+            win_condition: WinCondition::Normal,
+            heaps: vec![],
+            allowed_moves: vec![],
+            rule_set: RuleSet::Standard,
+        },
+    );
+    assert!(replied(&res, Err(PebblesError::InvalidMaxPebblesPerTurn)));
+}
+
+#[test]
+fn read_state() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+
+    let reply: StateReply = program
+        .read_state(StateQuery::Game(0))
+        .expect("Unable to read the state");
+    assert!(matches!(reply, StateReply::Game(None)));
+
+    let reply: StateReply = program
+        .read_state(StateQuery::Config)
+        .expect("Unable to read the state");
+    let StateReply::Config(config) = reply else {
+        panic!("Unexpected state reply: {reply:?}");
+    };
+    assert_eq!(config.pebbles_count, 10);
+    assert_eq!(config.max_pebbles_per_turn, 3);
+
+    program.send(USER, PebblesAction::StartGame(None));
+    program.send(USER, PebblesAction::Turn { id: 0, count: 2 });
+
+    let game = game(&program, 0);
+    assert_eq!(game.moves.len(), 2);
+    assert_eq!(game.moves[0].player, Player::User);
+    assert_eq!(game.moves[0].pebbles, 2);
+    assert_eq!(game.moves[0].remaining, 8);
+    assert_eq!(game.pebbles_remaining, 8 - last_program_move(&game));
+
+    let reply: StateReply = program
+        .read_state(StateQuery::LegalMoves(0))
+        .expect("Unable to read the state");
+    let StateReply::LegalMoves(moves) = reply else {
+        panic!("Unexpected state reply: {reply:?}");
+    };
+    let expected: Vec<_> = (1..=3.min(game.pebbles_remaining))
+        .map(|pebbles| (0, pebbles))
+        .collect();
+    assert_eq!(moves, expected);
+
+    let reply: StateReply = program
+        .read_state(StateQuery::Winner(0))
+        .expect("Unable to read the state");
+    assert!(matches!(reply, StateReply::Winner(None)));
+    assert_eq!(active_games(&program, USER), vec![0]);
+}
+
+#[test]
+fn concurrent_games() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+
+    program.send(USER, PebblesAction::StartGame(None));
+    // Each game may have its own parameters
+    let hard = params(DifficultyLevel::Hard, 20, 5);
+    let res = program.send(USER, PebblesAction::StartGame(Some(hard)));
+    let game_1 = game(&program, 1);
+    let started = PebblesEvent::GameStarted {
+        id: 1,
+        first_player: game_1.first_player.clone(),
+        remaining: game_1.pebbles_remaining,
+    };
+    assert!(replied(&res, Ok(started)));
+    program.send(RIVAL, PebblesAction::StartGame(None));
+    assert_eq!(active_games(&program, USER), vec![0, 1]);
+    assert_eq!(active_games(&program, RIVAL), vec![2]);
+    assert!(matches!(
+        game(&program, 0).difficulty,
+        DifficultyLevel::Easy
+    ));
+    assert!(matches!(
+        game(&program, 1).difficulty,
+        DifficultyLevel::Hard
+    ));
+
+    program.send(USER, PebblesAction::Turn { id: 0, count: 2 });
+    assert_eq!(game(&program, 0).moves.len(), 2);
+    assert_eq!(game(&program, 1).pebbles_count, 20);
+
+    program.send(USER, PebblesAction::GiveUp(0));
+    assert_eq!(active_games(&program, USER), vec![1]);
+
+    // Starting a new game drops the finished ones
+    program.send(USER, PebblesAction::StartGame(None));
+    assert_eq!(active_games(&program, USER), vec![1, 3]);
+    let reply: StateReply = program
+        .read_state(StateQuery::Game(0))
+        .expect("Unable to read the state");
+    assert!(matches!(reply, StateReply::Game(None)));
+}
+
+#[test]
+fn duel() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+
+    let res = program.send(RIVAL, PebblesAction::AcceptChallenge(USER.into()));
+    assert!(sent_to(&res, RIVAL, Err(PebblesError::NoChallenge)));
+
+    let res = program.send(
+        USER,
+        PebblesAction::Challenge(params(DifficultyLevel::Easy, 5, 2)),
+    );
+    assert!(replied(&res, Ok(PebblesEvent::ChallengeCreated)));
+    let res = program.send(USER, PebblesAction::AcceptChallenge(USER.into()));
+    assert!(replied(&res, Err(PebblesError::CannotChallengeSelf)));
+
+    // The challenger moves first with this seed
+    let res = program.send(RIVAL, PebblesAction::AcceptChallenge(USER.into()));
+    let started = PebblesEvent::DuelStarted {
+        challenger: USER.into(),
+        opponent: RIVAL.into(),
+        first_player: USER.into(),
+    };
+    assert!(sent_to(&res, RIVAL, Ok(started.clone())));
+    assert!(sent_to(&res, USER, Ok(started)));
+
+    let res = program.send(RIVAL, PebblesAction::DuelTurn { heap: 0, count: 1 });
+    assert!(sent_to(&res, RIVAL, Err(PebblesError::NotYourTurn)));
+
+    let res = program.send(USER, PebblesAction::DuelTurn { heap: 0, count: 2 });
+    let moved = PebblesEvent::DuelMove {
+        player: USER.into(),
+        heap: 0,
+        pebbles: 2,
+    };
+    assert!(sent_to(&res, USER, Ok(moved.clone())));
+    assert!(sent_to(&res, RIVAL, Ok(moved)));
+
+    program.send(RIVAL, PebblesAction::DuelTurn { heap: 0, count: 1 });
+    let res = program.send(USER, PebblesAction::DuelTurn { heap: 0, count: 2 });
+    assert!(sent_to(&res, USER, Ok(first_duel_won(USER))));
+    assert!(sent_to(&res, RIVAL, Ok(first_duel_won(USER))));
+
+    let reply: StateReply = program
+        .read_state(StateQuery::Duel(RIVAL.into()))
+        .expect("Unable to read the state");
+    let StateReply::Duel(Some(duel)) = reply else {
+        panic!("Unexpected state reply: {reply:?}");
+    };
+    assert_eq!(duel.challenger, USER.into());
+    assert_eq!(duel.opponent, RIVAL.into());
+    assert_eq!(duel.game.winner, Some(Player::User));
+    assert_eq!(duel.game.moves.len(), 3);
+
+    let reply: StateReply = program
+        .read_state(StateQuery::Challenges)
+        .expect("Unable to read the state");
+    assert!(matches!(reply, StateReply::Challenges(challenges) if challenges.is_empty()));
+}
+
+#[test]
+fn wager_against_the_program() {
+    let sys = System::new();
+    let program = init_with_bankroll(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+
+    let res = program.send_with_value(USER, PebblesAction::StartGame(None), STAKE);
+    let started = PebblesEvent::GameStarted {
+        id: 0,
+        first_player: Player::User,
+        remaining: 10,
+    };
+    assert!(replied(&res, Ok(started)));
+    assert_eq!(game(&program, 0).stake, STAKE);
+    assert_eq!(sys.balance_of(program.id()), BANKROLL + STAKE);
+
+    loop {
+        let remaining = game(&program, 0).pebbles_remaining;
+        let res = program.send(
+            USER,
+            PebblesAction::Turn {
+                id: 0,
+                count: remaining % 4,
+            },
+        );
+        if remaining <= 3 {
+            let payout = STAKE * payout_percent(&DifficultyLevel::Easy) / 100;
+            let won = first_won(Player::User, &DifficultyLevel::Easy);
+            assert!(replied(&res, Ok(won)));
+            assert!(replied(&res, Ok(PebblesEvent::Payout(payout))));
+            claim_payout(&sys, USER, payout);
+            assert_eq!(sys.balance_of(program.id()), BANKROLL + STAKE - payout);
+            break;
+        }
+    }
+
+    // Hard pays 300%: the bankroll cannot cover such a stake
+    let balance = sys.balance_of(program.id());
+    let res = program.send_with_value(
+        USER,
+        PebblesAction::Restart {
+            id: 0,
+            difficulty: DifficultyLevel::Hard,
+            pebbles_count: 10,
+            max_pebbles_per_turn: 3,
+            win_condition: WinCondition::Normal,
+            heaps: vec![],
+            allowed_moves: vec![],
+            rule_set: RuleSet::Standard,
+        },
+        BANKROLL,
+    );
+    assert!(replied(&res, Err(PebblesError::InsufficientBankroll)));
+    assert_eq!(sys.balance_of(program.id()), balance);
+}
+
+#[test]
+fn unaffordable_restart_keeps_the_game() {
+    let sys = System::new();
+    let program = init_with_bankroll(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+    program.send(USER, PebblesAction::StartGame(None));
+    program.send(USER, PebblesAction::Turn { id: 0, count: 2 });
+
+    // The failed restart neither gives up the unfinished game nor costs rating
+    let res = program.send_with_value(
+        USER,
+        PebblesAction::Restart {
+            id: 0,
+            difficulty: DifficultyLevel::Hard,
+            pebbles_count: 10,
+            max_pebbles_per_turn: 3,
+            win_condition: WinCondition::Normal,
+            heaps: vec![],
+            allowed_moves: vec![],
+            rule_set: RuleSet::Standard,
+        },
+        BANKROLL,
+    );
+    assert!(replied(&res, Err(PebblesError::InsufficientBankroll)));
+    let game = game(&program, 0);
+    assert!(game.winner.is_none());
+    assert_eq!(game.moves.len(), 2);
+    assert_eq!(rating(&program, USER), INITIAL_RATING);
+}
+
+#[test]
+fn duel_wager() {
+    let sys = System::new();
+    let init_params = PebblesInit {
+        turn_timeout: 100,
+        ..params(DifficultyLevel::Easy, 10, 3)
+    };
+    let program = init_with_bankroll(&sys, init_params, Player::User);
+
+    let res = program.send_with_value(
+        USER,
+        PebblesAction::Challenge(params(DifficultyLevel::Easy, 3, 3)),
+        STAKE,
+    );
+    assert!(replied(&res, Ok(PebblesEvent::ChallengeCreated)));
+
+    let res = program.send_with_value(RIVAL, PebblesAction::AcceptChallenge(USER.into()), 1);
+    assert!(sent_to(&res, RIVAL, Err(PebblesError::WrongStake)));
+
+    // The challenger moves first with this seed and takes the whole heap
+    program.send_with_value(RIVAL, PebblesAction::AcceptChallenge(USER.into()), STAKE);
+    assert_eq!(sys.balance_of(program.id()), BANKROLL + 2 * STAKE);
+    let res = program.send(USER, PebblesAction::DuelTurn { heap: 0, count: 3 });
+    assert!(sent_to(&res, RIVAL, Ok(first_duel_won(USER))));
+    assert!(replied(&res, Ok(PebblesEvent::Payout(2 * STAKE))));
+
+    claim_payout(&sys, USER, 2 * STAKE);
+    assert_eq!(sys.balance_of(program.id()), BANKROLL);
+}
+
+#[test]
+fn stakes_need_a_turn_timeout() {
+    let sys = System::new();
+    let program = init_with_bankroll(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+
+    // Without timeouts a stalled game would keep the funds forever
+    let challenge = PebblesAction::Challenge(params(DifficultyLevel::Easy, 3, 3));
+    let res = program.send_with_value(USER, challenge.clone(), STAKE);
+    assert!(replied(&res, Err(PebblesError::NoTurnTimeout)));
+    let res = program.send(USER, challenge);
+    assert!(replied(&res, Ok(PebblesEvent::ChallengeCreated)));
+
+    let create = |entry_fee| PebblesAction::CreateTournament {
+        format: TournamentFormat::RoundRobin,
+        params: params(DifficultyLevel::Easy, 3, 3),
+        entry_fee,
+        registration_deadline: sys.block_height() + 5,
+    };
+    let res = program.send(HOUSE, create(STAKE));
+    assert!(sent_to(&res, HOUSE, Err(PebblesError::NoTurnTimeout)));
+    let res = program.send(HOUSE, create(0));
+    assert!(sent_to(&res, HOUSE, Ok(PebblesEvent::TournamentCreated(0))));
+    assert_eq!(sys.balance_of(program.id()), BANKROLL);
+}
+
+#[test]
+fn turn_timeout() {
+    let sys = System::new();
+    let init_params = PebblesInit {
+        turn_timeout: 3,
+        ..params(DifficultyLevel::Easy, 10, 3)
+    };
+    let program = init(&sys, init_params, Player::User);
+
+    program.send(USER, PebblesAction::StartGame(None));
+    let res = program.send(USER, PebblesAction::CheckTimeout(0));
+    assert!(replied(&res, Err(PebblesError::NotTimedOut)));
+    program.send(USER, PebblesAction::Turn { id: 0, count: 1 });
+    assert!(game(&program, 0).winner.is_none());
+
+    let results = sys.spend_blocks(3);
+    assert!(results
+        .iter()
+        .any(|res| replied(res, Ok(first_won(Player::Program, &DifficultyLevel::Easy)))));
+    assert_eq!(game(&program, 0).winner, Some(Player::Program));
+
+    let res = program.send(USER, PebblesAction::Turn { id: 0, count: 1 });
+    assert!(replied(&res, Err(PebblesError::GameOver)));
+}
+
+#[test]
+fn duel_timeout() {
+    let sys = System::new();
+    let init_params = PebblesInit {
+        turn_timeout: 3,
+        ..params(DifficultyLevel::Easy, 10, 3)
+    };
+    let program = init(&sys, init_params, Player::User);
+
+    program.send(
+        USER,
+        PebblesAction::Challenge(params(DifficultyLevel::Easy, 5, 2)),
+    );
+    // The challenger moves first with this seed and stalls
+    program.send(RIVAL, PebblesAction::AcceptChallenge(USER.into()));
+
+    let results = sys.spend_blocks(3);
+    let won = Ok(first_duel_won(RIVAL));
+    assert!(results.iter().any(|res| replied(res, won.clone())));
+    assert!(results.iter().any(|res| sent_to(res, RIVAL, won.clone())));
+}
+
+#[test]
+fn leaderboard_and_stats() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+
+    program.send(USER, PebblesAction::StartGame(None));
+    while game(&program, 0).winner.is_none() {
+        let remaining = game(&program, 0).pebbles_remaining;
+        program.send(
+            USER,
+            PebblesAction::Turn {
+                id: 0,
+                count: remaining % 4,
+            },
+        );
+    }
+    program.send(USER, PebblesAction::StartGame(None));
+    program.send(USER, PebblesAction::GiveUp(1));
+    program.send(RIVAL, PebblesAction::StartGame(None));
+    program.send(RIVAL, PebblesAction::GiveUp(2));
+
+    let user_stats = Stats {
+        wins: 1,
+        losses: 0,
+        give_ups: 1,
+        current_streak: 0,
+        best_streak: 1,
+    };
+    let reply: StateReply = program
+        .read_state(StateQuery::Leaderboard(1))
+        .expect("Unable to read the state");
+    let StateReply::Leaderboard(leaderboard) = reply else {
+        panic!("Unexpected state reply: {reply:?}");
+    };
+    assert_eq!(leaderboard, vec![(USER.into(), user_stats.clone())]);
+
+    let reply: StateReply = program
+        .read_state(StateQuery::PlayerStats(USER.into()))
+        .expect("Unable to read the state");
+    let StateReply::PlayerStats(Some(stats)) = reply else {
+        panic!("Unexpected state reply: {reply:?}");
+    };
+    assert_eq!(stats.overall, user_stats);
+    assert_eq!(stats.by_difficulty.len(), 1);
+    assert_eq!(stats.by_difficulty[&DifficultyLevel::Easy], user_stats);
+}
+
+#[test]
+fn ratings() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+    let rating = |account| rating(&program, account);
+    assert_eq!(rating(USER), INITIAL_RATING);
+
+    // The challenger moves first with this seed and takes the whole heap
+    program.send(
+        RIVAL,
+        PebblesAction::Challenge(params(DifficultyLevel::Easy, 3, 3)),
+    );
+    program.send(USER, PebblesAction::AcceptChallenge(RIVAL.into()));
+    let res = program.send(RIVAL, PebblesAction::DuelTurn { heap: 0, count: 3 });
+    assert!(sent_to(&res, USER, Ok(first_duel_won(RIVAL))));
+    let change = rating_change(INITIAL_RATING, INITIAL_RATING, true);
+    assert_eq!(rating(RIVAL), INITIAL_RATING + change as u32);
+    assert_eq!(rating(USER), INITIAL_RATING - change as u32);
+
+    let before = rating(USER);
+    program.send(USER, PebblesAction::StartGame(None));
+    let res = program.send(USER, PebblesAction::GiveUp(0));
+    let lost = rating_change(before, bot_rating(&DifficultyLevel::Easy), false);
+    assert!(lost < 0);
+    assert!(replied(
+        &res,
+        Ok(PebblesEvent::Won {
+            winner: Player::Program,
+            rating_change: lost,
+        })
+    ));
+    assert_eq!(rating(USER), before.saturating_add_signed(lost));
+}
+
+#[test]
+fn matchmaking() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+    let queued = |program: &Program| {
+        let reply: StateReply = program
+            .read_state(StateQuery::Queue)
+            .expect("Unable to read the state");
+        match reply {
+            StateReply::Queue(queue) => queue.len(),
+            reply => panic!("Unexpected state reply: {reply:?}"),
+        }
+    };
+    let join = |pebbles_count| PebblesAction::JoinQueue {
+        rating_range: 50,
+        pebbles_count,
+        max_pebbles_per_turn: 2,
+    };
+
+    let res = program.send(USER, join(0));
+    assert!(replied(&res, Err(PebblesError::InvalidPebblesCount)));
+    let res = program.send(USER, join(5));
+    assert!(replied(&res, Ok(PebblesEvent::Queued)));
+    let res = program.send(RIVAL, join(6));
+    assert!(sent_to(&res, RIVAL, Ok(PebblesEvent::Queued)));
+    assert_eq!(queued(&program), 2);
+
+    // A lobby match withdraws the players' open challenges
+    program.send(
+        HOUSE,
+        PebblesAction::Challenge(params(DifficultyLevel::Easy, 5, 2)),
+    );
+    // The account that waited moves first with this seed
+    let res = program.send(HOUSE, join(5));
+    let matched = PebblesEvent::Matched {
+        waiting: USER.into(),
+        joined: HOUSE.into(),
+        first_player: USER.into(),
+    };
+    assert!(sent_to(&res, HOUSE, Ok(matched.clone())));
+    assert!(sent_to(&res, USER, Ok(matched)));
+    assert_eq!(queued(&program), 1);
+    let res = program.send(RIVAL, PebblesAction::AcceptChallenge(HOUSE.into()));
+    assert!(sent_to(&res, RIVAL, Err(PebblesError::NoChallenge)));
+    let res = program.send(USER, PebblesAction::DuelTurn { heap: 0, count: 2 });
+    assert!(sent_to(
+        &res,
+        HOUSE,
+        Ok(PebblesEvent::DuelMove {
+            player: USER.into(),
+            heap: 0,
+            pebbles: 2,
+        })
+    ));
+    let res = program.send(USER, join(5));
+    assert!(replied(&res, Err(PebblesError::GameInProgress)));
+
+    let res = program.send(RIVAL, PebblesAction::LeaveQueue);
+    assert!(sent_to(&res, RIVAL, Ok(PebblesEvent::QueueLeft)));
+    let res = program.send(RIVAL, PebblesAction::LeaveQueue);
+    assert!(sent_to(&res, RIVAL, Err(PebblesError::NotQueued)));
+
+    program.send(RIVAL, join(6));
+    let res = program.send(RIVAL, PebblesAction::CheckQueueExpiry(RIVAL.into()));
+    assert!(sent_to(&res, RIVAL, Err(PebblesError::NotTimedOut)));
+    let results = sys.spend_blocks(QUEUE_TTL);
+    assert!(results
+        .iter()
+        .any(|res| sent_to(res, RIVAL, Ok(PebblesEvent::QueueLeft))));
+    assert_eq!(queued(&program), 0);
+}
+
+#[test]
+fn single_elimination_tournament() {
+    let sys = System::new();
+    let init_params = PebblesInit {
+        turn_timeout: 100,
+        ..params(DifficultyLevel::Easy, 10, 3)
+    };
+    let program = init_with_bankroll(&sys, init_params, Player::User);
+    let deadline = sys.block_height() + 20;
+    let create = |registration_deadline| PebblesAction::CreateTournament {
+        format: TournamentFormat::SingleElimination,
+        params: params(DifficultyLevel::Easy, 3, 3),
+        entry_fee: STAKE,
+        registration_deadline,
+    };
+
+    let res = program.send(HOUSE, create(0));
+    assert!(sent_to(&res, HOUSE, Err(PebblesError::InvalidDeadline)));
+    let res = program.send(HOUSE, create(deadline + MAX_REGISTRATION_BLOCKS));
+    assert!(sent_to(&res, HOUSE, Err(PebblesError::InvalidDeadline)));
+    let res = program.send(HOUSE, create(deadline));
+    assert!(sent_to(&res, HOUSE, Ok(PebblesEvent::TournamentCreated(0))));
+    program.send(HOUSE, create(deadline));
+
+    let res = program.send_with_value(USER, PebblesAction::RegisterForTournament(0), 1);
+    assert!(replied(&res, Err(PebblesError::WrongStake)));
+    for account in [USER, RIVAL, HOUSE] {
+        let res = program.send_with_value(account, PebblesAction::RegisterForTournament(0), STAKE);
+        assert!(sent_to(&res, account, Ok(PebblesEvent::Registered(0))));
+    }
+    let res = program.send_with_value(USER, PebblesAction::RegisterForTournament(0), STAKE);
+    assert!(replied(&res, Err(PebblesError::AlreadyRegistered)));
+    // The only player of the second tournament gets the fee back
+    program.send_with_value(RIVAL, PebblesAction::RegisterForTournament(1), STAKE);
+    let res = program.send(USER, PebblesAction::StartTournament(0));
+    assert!(replied(&res, Err(PebblesError::NotTimedOut)));
+
+    let results = sys.spend_blocks(20);
+    assert!(results.iter().any(|res| sent_to(
+        res,
+        RIVAL,
+        Ok(PebblesEvent::TournamentCancelled(1))
+    )));
+    claim_payout(&sys, RIVAL, STAKE);
+    let res = program.send_with_value(USER, PebblesAction::RegisterForTournament(0), STAKE);
+    assert!(replied(&res, Err(PebblesError::RegistrationClosed)));
+
+    // With three players one of them skips the first round
+    let started = tournament(&program, 0);
+    assert_eq!(started.stage, TournamentStage::Running);
+    assert_eq!(started.matches.len(), 1);
+    let finished = play_tournament(&program, 0);
+    assert_eq!(finished.stage, TournamentStage::Finished);
+    assert_eq!(finished.round, 1);
+    assert_eq!(finished.winners.len(), 1);
+    assert_eq!(finished.wins.iter().sum::<u32>(), 2);
+
+    claim_payout(&sys, account(finished.winners[0]), 3 * STAKE);
+    assert_eq!(sys.balance_of(program.id()), BANKROLL);
+}
+
+#[test]
+fn round_robin_tournament() {
+    let sys = System::new();
+    let init_params = PebblesInit {
+        turn_timeout: 20,
+        ..params(DifficultyLevel::Easy, 10, 3)
+    };
+    let program = init_with_bankroll(&sys, init_params, Player::User);
+    program.send(
+        HOUSE,
+        PebblesAction::CreateTournament {
+            format: TournamentFormat::RoundRobin,
+            params: params(DifficultyLevel::Easy, 3, 3),
+            entry_fee: STAKE,
+            registration_deadline: sys.block_height() + 5,
+        },
+    );
+    for account in [USER, RIVAL, HOUSE] {
+        program.send_with_value(account, PebblesAction::RegisterForTournament(0), STAKE);
+    }
+    sys.spend_blocks(5);
+
+    // The player to move in the first match stalls and loses it
+    let started = tournament(&program, 0);
+    let first = &started.matches[0];
+    let (winner, loser) = match first.game.next_player() {
+        Player::User => (first.players.1, first.players.0),
+        _ => first.players,
+    };
+    let won = PebblesEvent::MatchWon {
+        tournament: 0,
+        winner,
+        rating_change: rating_change(INITIAL_RATING, INITIAL_RATING, true),
+    };
+    let results = sys.spend_blocks(20);
+    assert!(results
+        .iter()
+        .any(|res| sent_to(res, account(loser), Ok(won.clone()))));
+    let after_timeout = tournament(&program, 0);
+    assert_eq!(after_timeout.round, 1);
+    assert_eq!(after_timeout.wins.iter().sum::<u32>(), 1);
+
+    // Everyone plays everyone once
+    let finished = play_tournament(&program, 0);
+    assert_eq!(finished.stage, TournamentStage::Finished);
+    assert_eq!(finished.round, 2);
+    assert_eq!(finished.wins.iter().sum::<u32>(), 3);
+    let most = finished.wins.iter().max().copied();
+    for (player, wins) in finished.players.iter().zip(&finished.wins) {
+        assert_eq!(finished.winners.contains(player), Some(*wins) == most);
+    }
+
+    let prize = 3 * STAKE / finished.winners.len() as u128;
+    for winner in &finished.winners {
+        claim_payout(&sys, account(*winner), prize);
+    }
+    assert_eq!(sys.balance_of(program.id()), BANKROLL);
+}
+
+#[test]
+fn rematch() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+    program.send(USER, PebblesAction::StartGame(None));
+
+    let res = program.send(USER, PebblesAction::Rematch(0));
+    assert!(replied(&res, Err(PebblesError::GameInProgress)));
+    let res = program.send(USER, PebblesAction::Rematch(1));
+    assert!(replied(&res, Err(PebblesError::NoActiveGame)));
+
+    program.send(USER, PebblesAction::GiveUp(0));
+    program.send(USER, PebblesAction::Rematch(0));
+    let rematch = game(&program, 1);
+    assert_eq!(rematch.first_player, Player::Program);
+    assert_eq!(rematch.pebbles_count, 10);
+    assert_eq!(rematch.max_pebbles_per_turn, 3);
+    assert_eq!(rematch.moves.len(), 1);
+}
+
+#[test]
+fn wagered_rematch() {
+    let sys = System::new();
+    let program = init_with_bankroll(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+    program.send(USER, PebblesAction::StartGame(None));
+    program.send(USER, PebblesAction::GiveUp(0));
+
+    // The stake stays with the program and is lost with the game
+    program.send_with_value(USER, PebblesAction::Rematch(0), STAKE);
+    assert_eq!(game(&program, 1).stake, STAKE);
+    assert_eq!(sys.balance_of(program.id()), BANKROLL + STAKE);
+    program.send(USER, PebblesAction::GiveUp(1));
+    assert_eq!(sys.balance_of(program.id()), BANKROLL + STAKE);
+}
+
+#[test]
+fn best_of_three_series() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Hard, 10, 3), Player::User);
+    // Whoever moves first wins 10 pebbles with up to 3 per turn
+    let win = |id| loop {
+        let remaining = game(&program, id).pebbles_remaining;
+        let res = program.send(
+            USER,
+            PebblesAction::Turn {
+                id,
+                count: remaining % 4,
+            },
+        );
+        if remaining < 4 {
+            return res;
+        }
+    };
+
+    let res = program.send(
+        USER,
+        PebblesAction::StartSeries {
+            best_of: 2,
+            params: None,
+        },
+    );
+    assert!(replied(&res, Err(PebblesError::InvalidSeriesLength)));
+    let res = program.send(
+        USER,
+        PebblesAction::StartSeries {
+            best_of: 3,
+            params: None,
+        },
+    );
+    let started = PebblesEvent::GameStarted {
+        id: 0,
+        first_player: Player::User,
+        remaining: 10,
+    };
+    assert!(replied(&res, Ok(started)));
+
+    // The next game starts right away, with the program moving first
+    let res = win(0);
+    assert!(replied(
+        &res,
+        Ok(first_won(Player::User, &DifficultyLevel::Hard))
+    ));
+    let next = PebblesEvent::GameStarted {
+        id: 1,
+        first_player: Player::Program,
+        remaining: 8,
+    };
+    assert!(replied(&res, Ok(next)));
+    let res = program.send(
+        USER,
+        PebblesAction::Restart {
+            id: 1,
+            difficulty: DifficultyLevel::Easy,
+            pebbles_count: 10,
+            max_pebbles_per_turn: 3,
+            win_condition: WinCondition::Normal,
+            heaps: Vec::new(),
+            allowed_moves: Vec::new(),
+            rule_set: RuleSet::Standard,
+        },
+    );
+    assert!(replied(&res, Err(PebblesError::GameInProgress)));
+
+    program.send(USER, PebblesAction::GiveUp(1));
+    assert_eq!(game(&program, 2).first_player, Player::User);
+    let res = win(2);
+    let won = PebblesEvent::SeriesWon {
+        series: 0,
+        winner: Player::User,
+        score: (2, 1),
+    };
+    assert!(replied(&res, Ok(won)));
+
+    let reply: StateReply = program
+        .read_state(StateQuery::Series(0))
+        .expect("Unable to read the state");
+    match reply {
+        StateReply::Series(Some(series)) => {
+            assert_eq!(series.score, (2, 1));
+            assert_eq!(series.winner, Some(Player::User));
+        }
+        reply => panic!("Unexpected state reply: {reply:?}"),
+    }
+}
+
+#[test]
+fn elimination_byes_rotate() {
+    let sys = System::new();
+    let program = init(&sys, params(DifficultyLevel::Easy, 10, 3), Player::User);
+    program.send(
+        HOUSE,
+        PebblesAction::CreateTournament {
+            format: TournamentFormat::SingleElimination,
+            params: params(DifficultyLevel::Easy, 3, 3),
+            entry_fee: 0,
+            registration_deadline: sys.block_height() + 10,
+        },
+    );
+    for account in ACCOUNTS {
+        program.send(account, PebblesAction::RegisterForTournament(0));
+    }
+    sys.spend_blocks(10);
+
+    // Five players leave someone out of every round, never the same one twice in a row
+    let mut last_bye = None;
+    loop {
+        let tournament = tournament(&program, 0);
+        if tournament.stage != TournamentStage::Running {
+            break;
+        }
+        let bye = tournament.alive.iter().copied().find(|player| {
+            tournament.matches.iter().all(|tournament_match| {
+                ![tournament_match.players.0, tournament_match.players.1].contains(player)
+            })
+        });
+        assert!(bye.is_none() || bye != last_bye);
+        last_bye = bye;
+        play_round(&program, 0);
+    }
+    assert_eq!(tournament(&program, 0).round, 2);
+}